@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 pub struct AstroConf {
     pub id: u32,
     pub uav_radius: f32,
@@ -8,6 +10,12 @@ pub struct AstroConf {
                              // farther than this threshold risks losing contact.
                              // should be shorter than `msg_range`
     pub max_v: f32,  // how fast can uav fly, assuming isotropic
+    pub max_children: u32,  // how many children a node can have on the swarm tree
+    pub max_depth: u32,  // how deep the swarm tree can grow, root node has depth 1
+    pub parent_children_weight: f32,  // parent selection score added for each child of the candidate
+    pub parent_depth_weight: f32,  // parent selection score added for each level of depth of the candidate
+    pub reach_timeout: Duration,  // default time for each uav to reach its task position
+    pub task_timeout: Duration,  // default time for a task to succeed
 }
 
 impl AstroConf {
@@ -15,6 +23,17 @@ impl AstroConf {
         if self.uav_radius <= 0.0 {
             return Err(());
         }
+        if self.max_children < 1 || self.max_depth < 1 {
+            return Err(());
+        }
+        // nan would poison every parent score
+        let is_weight = |w: f32| w >= 0.0 && w.is_finite();
+        if !is_weight(self.parent_children_weight) || !is_weight(self.parent_depth_weight) {
+            return Err(());
+        }
+        if self.reach_timeout.is_zero() || self.task_timeout.is_zero() {
+            return Err(());
+        }
         Ok(())
     }
 }
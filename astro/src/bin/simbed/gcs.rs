@@ -3,14 +3,98 @@ use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
-use astro::kinetics::PosVec;
-use astro::control::msg::{NodeDesc, MsgBody, Msg, Line, Task};
+use astro::kinetics::{PosVec, Velocity};
+use astro::shape::Plane;
+use astro::shape::font::{text_to_lines, TextError, TextOptions};
+use astro::shape::formation::ring;
+use astro::control::msg::{NodeDesc, MsgBody, Msg, Coverage, Curve, Line, StationKeeping, StationPolicy,
+    Target, Task, TaskConstraints, TaskOutcome, TaskReport, Tracking, Transform, Waypoint, CensusMode, CensusQuery, CensusReply};
+
+pub const DEFAULT_CENSUS_BUDGET: Duration = Duration::from_millis(3000);
 
 #[derive(Deserialize, Serialize, Debug)]
 struct TaskInfo {
     task: Task,
     to_ids: Vec<u32>,
     wait_duration: Duration,
+    #[serde(default)]
+    cancel_duration: Option<Duration>,  // when to cancel the task, if ever
+    #[serde(default)]
+    after: Option<TaskDependency>,  // the task is dispatched only after another task has ended
+    #[serde(default)]
+    target: Option<TargetPlan>,  // motion of the target of a tracking task
+}
+
+// the target starts from its position in the task when the task is dispatched
+#[derive(Clone, Deserialize, Serialize, Debug)]
+struct TargetPlan {
+    path: Vec<Waypoint>,  // the target stops at the end of the path
+    interval: Duration,  // between updates sent to the swarm
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct TaskDependency {
+    tid: u32,
+    #[serde(default)]
+    outcome: Option<TaskOutcome>,  // required outcome of the other task, any outcome if None
+}
+
+// used to record task reports in the output file
+#[derive(Clone, Serialize, Debug)]
+pub struct ReportInfo {
+    running_duration: Duration,  // duration since simulation start, when the report is received
+    report: TaskReport,
+}
+
+// used to record census replies in the output file
+#[derive(Clone, Serialize, Debug)]
+pub struct CensusInfo {
+    running_duration: Duration,  // duration since simulation start, when the reply is received
+    root_id: u32,  // a disconnected swarm replies with its own root node
+    reply: CensusReply,
+}
+
+// periodic census queries sent by gcs
+pub struct CensusPlan {
+    pub interval: Duration,
+    pub mode: CensusMode,
+    pub page_size: u32,
+    pub to_ids: Vec<u32>,
+}
+
+struct CancelInfo {
+    tid: u32,
+    to_ids: Vec<u32>,
+    cancel_duration: Duration,
+}
+
+// a target updated until its task ends
+struct TargetInfo {
+    tid: u32,
+    to_ids: Vec<u32>,
+    p_init: PosVec,
+    plan: TargetPlan,
+    start_duration: Duration,  // when the task was dispatched
+    seq: u32,  // number of updates sent
+    station: bool,  // whether the task keeps station, so the target is still followed after success
+}
+
+impl TargetInfo {
+    // out: position and velocity of the target at time `t` since the task was dispatched
+    fn calc_motion(&self, t: Duration) -> (PosVec, Velocity) {
+        let mut t = t.as_secs_f32();
+        let mut prev = PosVec::zero();
+        for wp in &self.plan.path {
+            let leg = wp.offset - prev;
+            let leg_t = leg.norm() / wp.speed;
+            if t < leg_t {
+                return (self.p_init + prev + leg * (t / leg_t), leg / Duration::from_secs_f32(leg_t));
+            }
+            t -= leg_t;
+            prev = wp.offset;
+        }
+        (self.p_init + prev, Velocity::zero())
+    }
 }
 
 impl TaskInfo {
@@ -23,108 +107,254 @@ impl TaskInfo {
                         PosVec {x: 0.0, y: 10.0, z: 10.0},
                         PosVec {x: 0.0, y: 20.0, z: 10.0},
                     ],
+                    curves: vec![],
                     start: true,
                     end: true,
                 }],
                 duration: Duration::from_secs(10),
-                comm_point: None,
+                ..Default::default()
             },
             to_ids: vec![2, 3],
             wait_duration: Duration::from_secs(10),
+            cancel_duration: None,
+            after: None,
+            target: None,
         }
     }
 
+    // the line of `demo_simple_line`, kept after success and re-assigned whenever it degrades
+    pub fn demo_station() -> TaskInfo {
+        let mut ti = TaskInfo::demo_simple_line();
+        ti.task.station = Some(StationKeeping {
+            threshold: 1.0,
+            policy: StationPolicy::Reassign,
+        });
+        ti
+    }
+
     pub fn demo_lttr() -> TaskInfo {
         TaskInfo {
             task: Task {
                 id: 1,
-                lines: vec![  // bottom-left (0, 2, 2), letter size (0, 4, 8), stride (0, 5, 0).
-                    Line {  // 'L', (0, 2, 10)
+                lines: vec![  // letter size (0, 4, 8), stride (0, 5, 0), placed by the transform.
+                    Line {  // 'L', (0, 0, 8)
                         points: vec![
-                            PosVec {x: 0.0, y: 2.0, z: 10.0},
-                            PosVec {x: 0.0, y: 2.0, z: 2.0},
-                            PosVec {x: 0.0, y: 6.0, z: 2.0},
+                            PosVec {x: 0.0, y: 0.0, z: 8.0},
+                            PosVec {x: 0.0, y: 0.0, z: 0.0},
+                            PosVec {x: 0.0, y: 4.0, z: 0.0},
                         ],
+                        curves: vec![],
                         start: true,
                         end: true,
                     },
-                    Line {  // 'O', (0, 7, 10)
-                        points: Self::create_ellipse(9.0, 6.0, 2.0, 4.0, 20),
+                    Line {  // 'O', (0, 5, 8), an ellipse of four bezier curves
+                        points: vec![
+                            PosVec {x: 0.0, y: 7.0, z: 8.0},
+                            PosVec {x: 0.0, y: 9.0, z: 4.0},
+                            PosVec {x: 0.0, y: 7.0, z: 0.0},
+                            PosVec {x: 0.0, y: 5.0, z: 4.0},
+                            PosVec {x: 0.0, y: 7.0, z: 8.0},
+                        ],
+                        curves: vec![
+                            Some(Curve::Cubic {
+                                c1: PosVec {x: 0.0, y: 8.1046, z: 8.0},
+                                c2: PosVec {x: 0.0, y: 9.0, z: 6.2092},
+                            }),
+                            Some(Curve::Cubic {
+                                c1: PosVec {x: 0.0, y: 9.0, z: 1.7908},
+                                c2: PosVec {x: 0.0, y: 8.1046, z: 0.0},
+                            }),
+                            Some(Curve::Cubic {
+                                c1: PosVec {x: 0.0, y: 5.8954, z: 0.0},
+                                c2: PosVec {x: 0.0, y: 5.0, z: 1.7908},
+                            }),
+                            Some(Curve::Cubic {
+                                c1: PosVec {x: 0.0, y: 5.0, z: 6.2092},
+                                c2: PosVec {x: 0.0, y: 5.8954, z: 8.0},
+                            }),
+                        ],
                         start: false,
                         end: false,
                     },
-                    Line {  // 'V', (0, 12, 10)
+                    Line {  // 'V', (0, 10, 8)
                         points: vec![
-                            PosVec {x: 0.0, y: 12.0, z: 10.0},
-                            PosVec {x: 0.0, y: 14.0, z: 2.0},
-                            PosVec {x: 0.0, y: 16.0, z: 10.0},
+                            PosVec {x: 0.0, y: 10.0, z: 8.0},
+                            PosVec {x: 0.0, y: 12.0, z: 0.0},
+                            PosVec {x: 0.0, y: 14.0, z: 8.0},
                         ],
+                        curves: vec![],
                         start: true,
                         end: true,
                     },
-                    Line {  // 'E':'C', (0, 17, 10)
+                    Line {  // 'E':'C', (0, 15, 8)
                         points: vec![
-                            PosVec {x: 0.0, y: 21.0, z: 10.0},
-                            PosVec {x: 0.0, y: 17.0, z: 10.0},
-                            PosVec {x: 0.0, y: 17.0, z: 2.0},
-                            PosVec {x: 0.0, y: 21.0, z: 2.0},
+                            PosVec {x: 0.0, y: 19.0, z: 8.0},
+                            PosVec {x: 0.0, y: 15.0, z: 8.0},
+                            PosVec {x: 0.0, y: 15.0, z: 0.0},
+                            PosVec {x: 0.0, y: 19.0, z: 0.0},
                         ],
+                        curves: vec![],
                         start: true,
                         end: true,
                     },
-                    Line {  // 'E':'-', (0, 17, 10)
+                    Line {  // 'E':'-', (0, 15, 8)
                         points: vec![
-                            PosVec {x: 0.0, y: 18.0, z: 6.0},
-                            PosVec {x: 0.0, y: 21.0, z: 6.0},
+                            PosVec {x: 0.0, y: 16.0, z: 4.0},
+                            PosVec {x: 0.0, y: 19.0, z: 4.0},
                         ],
+                        curves: vec![],
                         start: false,
                         end: true,
                     },
                 ],
                 duration: Duration::from_secs(10),
-                comm_point: None,
+                transform: Some(Transform {  // bottom-left of the text
+                    anchor: PosVec {x: 0.0, y: 2.0, z: 2.0},
+                    relative: false,
+                    yaw: 0.0,
+                    pitch: 0.0,
+                    roll: 0.0,
+                    scale: 1.0,
+                }),
+                ..Default::default()
             },
             to_ids: vec![3],
             wait_duration: Duration::from_secs(10),
+            cancel_duration: None,
+            after: None,
+            target: None,
         }
     }
 
-    fn create_ellipse(cy: f32, cz: f32, ry: f32, rz: f32, n: u32) -> Vec<PosVec> {
-        let mut points: Vec<PosVec> = vec![];
-        for i in 0..(n + 1) {
-            let theta = (i as f32) * 2.0 * std::f32::consts::PI / (n as f32);
-            let sin = theta.sin();
-            let cos = theta.cos();
-            let r = (rz * ry) / ((rz * sin).powi(2) + (ry * cos).powi(2)).sqrt();
-            points.push(PosVec {
-                x: 0.0,
-                y: cy + r * sin,
-                z: cz + r * cos,
-            });
+    // a field of 20m by 16m swept at 6m altitude, in lanes 2m wide
+    pub fn demo_coverage() -> TaskInfo {
+        TaskInfo {
+            task: Task {
+                id: 0,
+                lines: vec![],
+                duration: Duration::from_secs(2),
+                coverage: Some(Coverage {
+                    area: vec![
+                        PosVec {x: 0.0, y: 0.0, z: 0.0},
+                        PosVec {x: 20.0, y: 0.0, z: 0.0},
+                        PosVec {x: 20.0, y: 16.0, z: 0.0},
+                        PosVec {x: 0.0, y: 16.0, z: 0.0},
+                    ],
+                    altitude: 6.0,
+                    width: 2.0,
+                    speed: 1.5,
+                    lanes: vec![],
+                }),
+                ..Default::default()
+            },
+            to_ids: vec![0],
+            wait_duration: Duration::from_secs(10),
+            cancel_duration: None,
+            after: None,
+            target: None,
         }
-        points
+    }
+
+    // a ring of 5m radius kept 6m above a target driving around a square
+    pub fn demo_tracking() -> TaskInfo {
+        let leg = |x: f32, y: f32| Waypoint { offset: PosVec {x, y, z: 0.0}, speed: 1.0 };
+        TaskInfo {
+            task: Task {
+                id: 0,
+                lines: ring(5.0, 6.0),
+                duration: Duration::from_secs(30),
+                tracking: Some(Tracking {
+                    target: PosVec {x: 0.0, y: 10.0, z: 0.0},
+                    heading: false,
+                }),
+                ..Default::default()
+            },
+            to_ids: vec![0],
+            wait_duration: Duration::from_secs(10),
+            cancel_duration: None,
+            after: None,
+            target: Some(TargetPlan {
+                path: vec![leg(15.0, 0.0), leg(15.0, 15.0), leg(0.0, 15.0), leg(0.0, 0.0)],
+                interval: Duration::from_secs(1),
+            }),
+        }
+    }
+
+    // the text spelled in letters 8m high, in the same place as `demo_lttr`
+    pub fn from_text(text: &str) -> Result<TaskInfo, TextError> {
+        let opts = TextOptions {
+            height: 8.0,
+            spacing: 1.0,
+            plane: Plane::Yz,
+            origin: PosVec {x: 0.0, y: 2.0, z: 2.0},
+        };
+        Ok(TaskInfo {
+            task: Task {
+                id: 1,
+                lines: text_to_lines(text, &opts)?,
+                duration: Duration::from_secs(10),
+                ..Default::default()
+            },
+            to_ids: vec![0],
+            wait_duration: Duration::from_secs(10),
+            cancel_duration: None,
+            after: None,
+            target: None,
+        })
     }
 }
 
 pub struct Gcs {
     tasks: Vec<TaskInfo>,
+    cancels: Vec<CancelInfo>,
+    reports: Vec<ReportInfo>,
+    num_reports_output: usize,
+    census_plan: Option<CensusPlan>,
+    census_qid: u32,  // id of the latest census query
+    last_census_duration: Duration,
+    census: Vec<CensusInfo>,  // census replies received since last output
+    targets: Vec<TargetInfo>,
 }
 
 impl Gcs {
-    pub fn new(task_book: &String) -> Gcs {
+    // tasks failing validation are dropped, they would be rejected by the root node anyway
+    pub fn new(task_book: &String, census_plan: Option<CensusPlan>, constraints: &TaskConstraints) -> Gcs {
         let mut tasks: Vec<TaskInfo> = vec![];
         if task_book.is_empty() || task_book == "demo_simple_line" {
             tasks.push(TaskInfo::demo_simple_line());
+        } else if task_book == "demo_station" {
+            tasks.push(TaskInfo::demo_station());
         } else if task_book == "demo_lttr" {
             tasks.push(TaskInfo::demo_lttr());
+        } else if task_book == "demo_coverage" {
+            tasks.push(TaskInfo::demo_coverage());
+        } else if task_book == "demo_tracking" {
+            tasks.push(TaskInfo::demo_tracking());
+        } else if let Some(text) = task_book.strip_prefix("text:") {
+            match TaskInfo::from_text(text) {
+                Ok(ti) => tasks.push(ti),
+                Err(err) => println!("gcs: text {:?} rejected: {:?}", text, err),
+            }
         } else {
             for line in read_to_string(task_book).unwrap().lines() {
                 let task_info: TaskInfo = serde_json::from_str(line).unwrap();
                 tasks.push(task_info);
             }
         }
+        tasks.retain(|ti| match ti.task.validate(constraints) {
+            Ok(()) => true,
+            Err(err) => { println!("gcs: task {} rejected: {:?}", ti.task.id, err); false },
+        });
         Gcs {
             tasks,
+            cancels: vec![],
+            reports: vec![],
+            num_reports_output: 0,
+            census_plan,
+            census_qid: 0,
+            last_census_duration: Duration::ZERO,
+            census: vec![],
+            targets: vec![],
         }
     }
 
@@ -132,6 +362,16 @@ impl Gcs {
         let mut msgs: Vec<Msg> = vec![];
         let mut dispatched: Vec<u32> = vec![];
         for ti in &self.tasks {
+            match self.check_dependency(ti) {
+                None => { continue; },  // dependency not ended yet
+                Some(false) => {
+                    println!("gcs: task {} skipped, as task {} ended with another outcome",
+                        ti.task.id, ti.after.as_ref().unwrap().tid);
+                    dispatched.push(ti.task.id);
+                    continue;
+                },
+                Some(true) => (),
+            }
             if running_duration >= ti.wait_duration {
                 msgs.push(Msg {
                     sender: NodeDesc::get_gcs_desc(),
@@ -139,9 +379,143 @@ impl Gcs {
                     body: MsgBody::Task(ti.task.clone()),
                 });
                 dispatched.push(ti.task.id);
+                if let Some(cancel_duration) = ti.cancel_duration {
+                    self.cancels.push(CancelInfo {
+                        tid: ti.task.id,
+                        to_ids: ti.to_ids.clone(),
+                        cancel_duration,
+                    });
+                }
+                if let (Some(trk), Some(plan)) = (&ti.task.tracking, &ti.target) {
+                    self.targets.push(TargetInfo {
+                        tid: ti.task.id,
+                        to_ids: ti.to_ids.clone(),
+                        p_init: trk.target,
+                        plan: plan.clone(),
+                        start_duration: running_duration,
+                        seq: 0,
+                        station: ti.task.station.is_some(),
+                    });
+                }
             }
         }
         self.tasks.retain(|t| !dispatched.contains(&t.task.id));
+        let mut cancelled: Vec<u32> = vec![];
+        for ci in &self.cancels {
+            if running_duration >= ci.cancel_duration {
+                msgs.push(Msg {
+                    sender: NodeDesc::get_gcs_desc(),
+                    to_ids: ci.to_ids.clone(),
+                    body: MsgBody::CancelTask(ci.tid),
+                });
+                cancelled.push(ci.tid);
+            }
+        }
+        self.cancels.retain(|c| !cancelled.contains(&c.tid));
+        for tgt in &mut self.targets {
+            if running_duration >= tgt.start_duration + tgt.plan.interval * tgt.seq {
+                let (p, v) = tgt.calc_motion(running_duration - tgt.start_duration);
+                tgt.seq += 1;
+                msgs.push(Msg {
+                    sender: NodeDesc::get_gcs_desc(),
+                    to_ids: tgt.to_ids.clone(),
+                    body: MsgBody::Target(Target { tid: tgt.tid, seq: tgt.seq, p, v }),
+                });
+            }
+        }
+        if let Some(msg) = self.maybe_generate_census_query(running_duration) {
+            msgs.push(msg);
+        }
         msgs
     }
+
+    fn maybe_generate_census_query(&mut self, running_duration: Duration) -> Option<Msg> {
+        let plan = self.census_plan.as_ref()?;
+        if running_duration < self.last_census_duration + plan.interval {
+            return None;
+        }
+        self.census_qid += 1;
+        self.last_census_duration = running_duration;
+        Some(Msg {
+            sender: NodeDesc::get_gcs_desc(),
+            to_ids: plan.to_ids.clone(),
+            body: MsgBody::CensusQuery(CensusQuery {
+                qid: self.census_qid,
+                mode: plan.mode,
+                page_size: plan.page_size,
+                budget: DEFAULT_CENSUS_BUDGET,
+            }),
+        })
+    }
+
+    // out: None if the dependency has not ended yet,
+    //      Some(true) if the dependency is met, Some(false) if it can never be met.
+    fn check_dependency(&self, ti: &TaskInfo) -> Option<bool> {
+        let dep = match &ti.after {
+            None => { return Some(true); },
+            Some(dep) => dep,
+        };
+        // a preempted task may still run again later
+        let report = self.reports.iter().map(|ri| &ri.report).find(|r| r.tid == dep.tid && match dep.outcome {
+            Some(outcome) => r.outcome == outcome || r.outcome != TaskOutcome::Preempted,
+            None => r.outcome != TaskOutcome::Preempted,
+        })?;
+        Some(dep.outcome.is_none_or(|outcome| report.outcome == outcome))
+    }
+
+    pub fn receive_msgs(&mut self, msgs: &Vec<Msg>, running_duration: Duration) {
+        for msg in msgs {
+            match &msg.body {
+                MsgBody::TaskCancelled(tid) => {
+                    println!("gcs: cancellation of task {} acknowledged by {}", tid, msg.sender.get_id());
+                    self.targets.retain(|t| t.tid != *tid);
+                },
+                MsgBody::TaskReport(report) => {
+                    match report.reason {
+                        Some(reason) => println!("gcs: task {} reported {:?} ({:?}) by {}",
+                            report.tid, report.outcome, reason, msg.sender.get_id()),
+                        None => println!("gcs: task {} reported {:?} by {}", report.tid, report.outcome, msg.sender.get_id()),
+                    }
+                    match report.outcome {
+                        TaskOutcome::Preempted => (),
+                        TaskOutcome::Success => self.targets.retain(|t| t.tid != report.tid || t.station),
+                        _ => self.targets.retain(|t| t.tid != report.tid),
+                    }
+                    if let Some(coverage) = report.coverage {
+                        println!("gcs: task {} covered {:.0}% of its area", report.tid, coverage * 100.0);
+                    }
+                    self.reports.push(ReportInfo {
+                        running_duration,
+                        report: report.clone(),
+                    });
+                },
+                MsgBody::FormationReport(report) => {
+                    println!("gcs: formation of task {} {} (rms {:.2}m, max {:.2}m) by {}", report.tid,
+                        if report.degraded { "degraded" } else { "restored" }, report.rms, report.max, msg.sender.get_id());
+                },
+                MsgBody::CensusReply(reply) => {
+                    println!("gcs: census {} page {}/{} replied by {}",
+                        reply.qid, reply.page + 1, reply.pages, msg.sender.get_id());
+                    self.census.push(CensusInfo {
+                        running_duration,
+                        root_id: msg.sender.get_id(),
+                        reply: reply.clone(),
+                    });
+                },
+                _ => (),
+            }
+        }
+    }
+
+    // reports received since last call
+    pub fn take_new_reports(&mut self) -> Vec<ReportInfo> {
+        let new_reports = self.reports[self.num_reports_output..].to_vec();
+        self.num_reports_output = self.reports.len();
+        new_reports
+    }
+
+    // census replies received since last call
+    pub fn take_new_census(&mut self) -> Vec<CensusInfo> {
+        std::mem::take(&mut self.census)
+    }
 }
\ No newline at end of file
@@ -1,4 +1,8 @@
-use clap::Parser;
+use std::time::Duration;
+
+use clap::{Parser, ValueEnum};
+
+use astro::control::msg::CensusMode;
 
 mod gcs;
 mod simbed;
@@ -6,8 +10,15 @@ mod uavconf;
 mod uavsim;
 mod uav;
 
+use gcs::CensusPlan;
 use simbed::SimBed;
 
+#[derive(Clone, Copy, ValueEnum, Debug)]
+enum CensusArg {
+    Full,
+    Summary,
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
@@ -17,10 +28,29 @@ struct Args {
     num_uav: u32,
     #[arg(long, default_value_t = String::new())]
     task_book: String,
+    #[arg(long, default_value_t = 0)]
+    census_interval: u64,  // seconds between census queries of gcs, 0 for no census
+    #[arg(long, value_enum, default_value_t = CensusArg::Summary)]
+    census_mode: CensusArg,
+    #[arg(long, default_value_t = 0)]
+    census_page_size: u32,  // 0 for no paging
 }
 
 fn main() {
     let args = Args::parse();
-    let mut simbed = SimBed::new(args.num_uav, &args.astro_bin, &args.task_book);
+    let census_plan = if args.census_interval > 0 {
+        Some(CensusPlan {
+            interval: Duration::from_secs(args.census_interval),
+            mode: match args.census_mode {
+                CensusArg::Full => CensusMode::Full,
+                CensusArg::Summary => CensusMode::Summary,
+            },
+            page_size: args.census_page_size,
+            to_ids: (0..args.num_uav).collect(),
+        })
+    } else {
+        None
+    };
+    let mut simbed = SimBed::new(args.num_uav, &args.astro_bin, &args.task_book, census_plan);
     simbed.run_sim_loop();
 }
\ No newline at end of file
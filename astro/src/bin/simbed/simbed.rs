@@ -9,13 +9,13 @@ use rand::{thread_rng, seq::SliceRandom};
 use serde::Serialize;
 
 use astro::kinetics::PosVec;
-use astro::control::msg::Msg;
+use astro::control::msg::{Msg, TaskConstraints, GCS_ID};
 
 use crate::uavsim::UavInfo;
 
-use super::gcs::Gcs;
+use super::gcs::{CensusInfo, CensusPlan, Gcs, ReportInfo};
 use super::uav::Uav;
-use super::uavconf::UavConf;
+use super::uavconf::{UavConf, DEFAULT_UAV_RADIUS};
 use super::uavsim::{UavSim, MsgPack};
 
 pub const SIM_LOOP_INTERVAL_MIN: Duration = Duration::from_millis(30);
@@ -30,6 +30,8 @@ pub const DEFAULT_OUTPUT_DURATION: Duration = Duration::from_secs(2);
 pub struct SwarmInfo {
     running_duration: Duration,  // duration since simulation start
     uavs: Vec<UavInfo>,
+    reports: Vec<ReportInfo>,  // task reports received by gcs since last record
+    census: Vec<CensusInfo>,  // census replies received by gcs since last record
 }
 
 // provide simulation support for a UAV swarm including:
@@ -46,7 +48,8 @@ pub struct SimBed {
 }
 
 impl SimBed {
-    pub fn new(num_uav: u32, astro_bin: &String, task_book: &String) -> SimBed {
+    pub fn new(num_uav: u32, astro_bin: &String, task_book: &String,
+               census_plan: Option<CensusPlan>) -> SimBed {
         let init_p_vec = Self::generate_initial_positions(num_uav);
         let mut uavs: Vec<Uav> = vec![];
         for id in 0..num_uav {
@@ -63,7 +66,10 @@ impl SimBed {
         SimBed {
             sim_start_t: now,
             uavs,
-            gcs: Gcs::new(task_book),
+            gcs: Gcs::new(task_book, census_plan, &TaskConstraints {
+                min_spacing: 2.0 * DEFAULT_UAV_RADIUS,
+                swarm_size: Some(num_uav),
+            }),
             writer,
             output_duration,
             last_output_t: now - output_duration,
@@ -114,10 +120,12 @@ impl SimBed {
         Self::update_kinetics(&mut uav_sims);
         let msg_packs = Self::collect_message_packs_and_update_nids(&mut uav_sims);
         Self::dispose_message_packs(&uav_sims, &msg_packs);
+        self.gcs.receive_msgs(&Self::collect_gcs_messages(&msg_packs), running_duration);
         Self::dispose_gcs_messages(&uav_sims, &self.gcs.generate_gcs_msgs(running_duration));
 
         if now - self.last_output_t > self.output_duration {
-            Self::output_swarm_info(&uav_sims, &mut self.writer, running_duration);
+            Self::output_swarm_info(&uav_sims, self.gcs.take_new_reports(), self.gcs.take_new_census(),
+                &mut self.writer, running_duration);
             self.last_output_t = now;
             self.output_duration *= 2;
             if self.output_duration > DEFAULT_OUTPUT_DURATION {
@@ -150,13 +158,22 @@ impl SimBed {
         }
     }
 
+    fn collect_gcs_messages(msg_packs: &Vec<MsgPack>) -> Vec<Msg> {
+        let mut msgs: Vec<Msg> = vec![];
+        for pack in msg_packs {
+            msgs.append(&mut pack.get_msgs_sent_to(GCS_ID));
+        }
+        msgs
+    }
+
     fn dispose_gcs_messages(sims: &Vec<&mut UavSim>, gcs_msgs: &Vec<Msg>) {
         for sim in sims {
             sim.dispose_comm_msgs(gcs_msgs);
         }
     }
 
-    fn output_swarm_info(sims: &Vec<&mut UavSim>, writer: &mut BufWriter<File>, running_duration: Duration) {
+    fn output_swarm_info(sims: &Vec<&mut UavSim>, reports: Vec<ReportInfo>, census: Vec<CensusInfo>,
+                         writer: &mut BufWriter<File>, running_duration: Duration) {
         let mut uavs: Vec<UavInfo> = vec![];
         for sim in sims {
             uavs.push(sim.get_info());
@@ -164,6 +181,8 @@ impl SimBed {
         let swarm_info = SwarmInfo {
             running_duration,
             uavs,
+            reports,
+            census,
         };
         let data = serde_json::to_string(&swarm_info).unwrap();
         writer.write_all(data.as_bytes()).unwrap();
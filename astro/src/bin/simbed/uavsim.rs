@@ -28,6 +28,12 @@ impl MsgPack {
     pub fn get_source_p(&self) -> &PosVec {
         &self.p
     }
+
+    // messages in this pack sent specifically to the receiver of given id
+    pub fn get_msgs_sent_to(&self, id: u32) -> Vec<Msg> {
+        self.data_vec.iter().map(|data| serde_json::from_str::<Msg>(data).unwrap())
+            .filter(|msg| msg.to_ids.contains(&id)).collect()
+    }
 }
 
 // used to record uav status in a file
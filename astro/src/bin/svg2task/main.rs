@@ -0,0 +1,93 @@
+use std::fs::read_to_string;
+use std::time::Duration;
+
+use clap::{CommandFactory, Parser, ValueEnum};
+use clap::error::ErrorKind;
+use serde_json::json;
+
+use astro::control::msg::{Task, Transform};
+use astro::kinetics::PosVec;
+use astro::shape::Plane;
+use astro::shape::svg::{read_svg_lines, SvgOptions};
+
+// converts svg drawings into a task book of simbed, one task per drawing, printed to stdout.
+// the drawings are placed by the transform of the tasks, their bottom-left at the anchor.
+
+#[derive(Clone, Copy, ValueEnum, Debug)]
+enum PlaneArg {
+    Xy,
+    Xz,
+    Yz,
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "svg2task", version, about, long_about = None)]
+struct Args {
+    svg_files: Vec<String>,
+    #[arg(long, value_enum, default_value_t = PlaneArg::Yz)]
+    plane: PlaneArg,
+    #[arg(long, default_value_t = 0.1)]
+    scale: f32,  // m per svg user unit
+    #[arg(long, default_value_t = 0.05)]
+    tolerance: f32,  // m, flattening tolerance of curves
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true, default_values_t = [0.0, 0.0, 2.0])]
+    anchor: Vec<f32>,  // m, x,y,z
+    #[arg(long)]
+    relative: bool,  // whether the anchor is relative to the root node
+    #[arg(long, default_value_t = 1)]
+    tid: u32,  // id of the first task, following tasks count up
+    #[arg(long, value_delimiter = ',', default_value = "0")]
+    to_ids: Vec<u32>,
+    #[arg(long, default_value_t = 10.0)]
+    wait: f32,  // seconds before the first task is sent
+    #[arg(long, default_value_t = 60.0)]
+    interval: f32,  // seconds between tasks
+    #[arg(long, default_value_t = 10.0)]
+    duration: f32,  // seconds to hold each shape
+}
+
+fn usage_error(msg: &str) -> ! {
+    Args::command().error(ErrorKind::ValueValidation, msg).exit()
+}
+
+fn main() {
+    let args = Args::parse();
+    if args.anchor.len() != 3 {
+        usage_error("anchor needs x,y,z");
+    }
+    let secs = |s: f32| Duration::try_from_secs_f32(s).unwrap_or_else(|_| usage_error("durations must be non-negative"));
+    let (duration, wait, interval) = (secs(args.duration), secs(args.wait), secs(args.interval));
+    let opts = SvgOptions {
+        plane: match args.plane {
+            PlaneArg::Xy => Plane::Xy,
+            PlaneArg::Xz => Plane::Xz,
+            PlaneArg::Yz => Plane::Yz,
+        },
+        scale: args.scale,
+        tolerance: args.tolerance,
+    };
+    for (i, file) in args.svg_files.iter().enumerate() {
+        let svg = read_to_string(file).unwrap_or_else(|err| usage_error(&format!("cannot read {}: {}", file, err)));
+        let lines = read_svg_lines(&svg, &opts).unwrap_or_else(|err| usage_error(&format!("{}: {:?}", file, err)));
+        let task = Task {
+            id: args.tid + i as u32,
+            lines,
+            duration,
+            transform: Some(Transform {
+                anchor: PosVec { x: args.anchor[0], y: args.anchor[1], z: args.anchor[2] },
+                relative: args.relative,
+                yaw: 0.0,
+                pitch: 0.0,
+                roll: 0.0,
+                scale: 1.0,
+            }),
+            ..Default::default()
+        };
+        let task_info = json!({
+            "task": task,
+            "to_ids": args.to_ids,
+            "wait_duration": wait + interval * i as u32,
+        });
+        println!("{}", task_info);
+    }
+}
@@ -6,14 +6,17 @@ use super::kinetics::{PosVec, Velocity};
 
 pub mod msg;
 
+mod census;
 mod collivoid;
 mod contacts;
+mod delivery;
 mod nm;
 mod tm;
 
 use msg::Msg;
 use collivoid::ColliVoid;
 use contacts::Contacts;
+use delivery::Delivery;
 use nm::NodeManager;
 
 pub const DEFAULT_BROADCASTING_DURATION: Duration = Duration::from_millis(50);
@@ -21,6 +24,7 @@ pub const DEFAULT_BROADCASTING_DURATION: Duration = Duration::from_millis(50);
 pub struct Control {
     conf: Rc<AstroConf>,
     contacts: Contacts,
+    delivery: Delivery,
     nm: NodeManager,
     collivoid: ColliVoid,
     broadcasting_duration: Duration,
@@ -34,6 +38,7 @@ impl Control {
         Control {
             conf: conf.clone(),
             contacts: Contacts::new(p, conf.contact_range),
+            delivery: Delivery::new(),
             nm: NodeManager::new_root_node(conf, p, v),
             collivoid: ColliVoid::new(conf),
             broadcasting_duration,
@@ -48,10 +53,15 @@ impl Control {
 
         // keep only those messages sent specifically to this node
         msgs.retain(|m| m.to_ids.contains(&self.conf.id));
+        // acknowledge event messages, and drop their duplicates
+        let now = Instant::now();
+        let (msgs, mut acks) = self.delivery.receive(&self.nm.generate_node_desc(), &msgs, now);
         // node manager update, generating output messages and giving an appropriate velocity
-        let (next_v, mut msgs_out) = self.nm.update_node(p, v, &rm, &msgs, &neighbours);
+        let (next_v, msgs_out) = self.nm.update_node(p, v, &rm, &msgs.iter().collect(), &neighbours);
+        // event messages are sent reliably
+        let mut msgs_out = self.delivery.send(&self.nm.generate_node_desc(), msgs_out, &rm, now);
+        msgs_out.append(&mut acks);
 
-        let now = Instant::now();
         if now - self.last_broadcasting_t >= self.broadcasting_duration {
             msgs_out.push(Msg::new_empty_msg(self.nm.generate_node_desc()));
             self.last_broadcasting_t = now;
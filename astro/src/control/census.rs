@@ -0,0 +1,67 @@
+// census of the swarm, aggregated up the swarm tree.
+// a census query from gcs travels up to the root node, and then fans out down the tree.
+// each node replies to its parent with the aggregate of its subswarm,
+// after all its children have replied, or after its time budget runs out.
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use super::msg::{CensusData, CensusQuery};
+
+pub const CENSUS_LEVEL_MARGIN: Duration = Duration::from_millis(300);
+pub const CENSUS_MIN_BUDGET: Duration = Duration::from_millis(300);
+
+pub struct CensusCollector {
+    query: CensusQuery,
+    reply_to: u32,  // parent id, or gcs id for root node
+    waiting: HashSet<u32>,  // children not replied yet
+    data: CensusData,
+    deadline: Instant,
+}
+
+impl CensusCollector {
+    pub fn new(query: &CensusQuery, reply_to: u32, own_data: CensusData, children: Vec<u32>, now: Instant)
+    -> CensusCollector {
+        CensusCollector {
+            query: query.clone(),
+            reply_to,
+            waiting: children.into_iter().collect(),
+            data: own_data,
+            deadline: now + query.budget,
+        }
+    }
+
+    pub fn get_qid(&self) -> u32 {
+        self.query.qid
+    }
+
+    pub fn get_reply_to(&self) -> u32 {
+        self.reply_to
+    }
+
+    pub fn get_page_size(&self) -> u32 {
+        self.query.page_size
+    }
+
+    // the query passed on to children, leaving this node some time to reply after children do
+    pub fn get_child_query(&self) -> CensusQuery {
+        let mut query = self.query.clone();
+        query.budget = query.budget.saturating_sub(CENSUS_LEVEL_MARGIN).max(CENSUS_MIN_BUDGET);
+        query
+    }
+
+    pub fn add_reply(&mut self, cid: u32, data: CensusData) {
+        if self.waiting.remove(&cid) {
+            self.data.merge(data);
+        }
+    }
+
+    // a lost or slow child shall not block the census, its subswarm is left out after timeout
+    pub fn is_done(&self, now: Instant) -> bool {
+        self.waiting.is_empty() || now >= self.deadline
+    }
+
+    pub fn into_data(self) -> CensusData {
+        self.data
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use super::msg::{Msg, MsgBody, NodeDesc, GCS_ID};
+
+pub const DEFAULT_RETRANSMIT_TIMEOUT: Duration = Duration::from_millis(300);
+pub const DEFAULT_MAX_RETRANSMITS: u32 = 4;
+pub const DEFAULT_DEDUP_DURATION: Duration = Duration::from_secs(10);
+
+// an event message waiting for acks
+struct Pending {
+    msg: Msg,  // wrapped as reliable, sent to the receivers that have not acknowledged it yet
+    retransmits: u32,
+    timeout: Duration,  // doubled after each retransmission
+    next_t: Instant,
+}
+
+// deliver event messages between uavs reliably.
+// each event message gets an id, and is retransmitted with backoff until every receiver acknowledges it,
+// or until the retransmissions run out. the receiver acknowledges every copy,
+// but passes only the first one on to the node manager.
+//
+// messages to or from ground control station, broadcasts and state carrying messages are sent as they are.
+pub struct Delivery {
+    next_mid: u32,
+    pending: HashMap<u32, Pending>,
+    seen: HashMap<(u32, u32), Instant>,  // (sender id, message id) of the event messages received
+    retransmit_timeout: Duration,
+    max_retransmits: u32,
+    dedup_duration: Duration,  // should be longer than a sender keeps retransmitting
+}
+
+impl Delivery {
+    pub fn new() -> Delivery {
+        Delivery {
+            next_mid: 0,
+            pending: HashMap::new(),
+            seen: HashMap::new(),
+            retransmit_timeout: DEFAULT_RETRANSMIT_TIMEOUT,
+            max_retransmits: DEFAULT_MAX_RETRANSMITS,
+            dedup_duration: DEFAULT_DEDUP_DURATION,
+        }
+    }
+
+    // `msgs_in`: messages sent to this node, ordered by arrival time
+    //
+    // acks are consumed, reliable messages are acknowledged and unwrapped, and duplicates dropped.
+    //
+    // `msgs`: messages to pass on to the node manager, in the order of arrival
+    // `acks`: acks to send back
+    pub fn receive(&mut self, desc: &NodeDesc, msgs_in: &Vec<&Msg>, now: Instant) -> (Vec<Msg>, Vec<Msg>) {
+        self.seen.retain(|_, t| now - *t <= self.dedup_duration);
+        let mut msgs: Vec<Msg> = vec![];
+        let mut acks: Vec<Msg> = vec![];
+        for msg in msgs_in {
+            let sid = msg.sender.get_id();
+            match &msg.body {
+                MsgBody::Ack(mid) => self.remove_receiver(*mid, sid),
+                MsgBody::Reliable(mid, body) => {
+                    acks.push(Msg {
+                        sender: desc.clone(),
+                        to_ids: vec![sid],
+                        body: MsgBody::Ack(*mid),
+                    });
+                    if self.seen.insert((sid, *mid), now).is_none() {
+                        msgs.push(Msg {
+                            sender: msg.sender.clone(),
+                            to_ids: msg.to_ids.clone(),
+                            body: (**body).clone(),
+                        });
+                    }
+                },
+                _ => msgs.push((*msg).clone()),
+            }
+        }
+        (msgs, acks)
+    }
+
+    // `msgs_out`: messages generated by the node manager
+    // `rm`: nodes newly out of contact, which are not waited for any more
+    //
+    // out: messages to send, including retransmissions of event messages not acknowledged yet
+    pub fn send(&mut self, desc: &NodeDesc, msgs_out: Vec<Msg>, rm: &[u32], now: Instant) -> Vec<Msg> {
+        let mut msgs: Vec<Msg> = vec![];
+        for msg in msgs_out {
+            if !msg.body.is_event() || msg.to_ids.is_empty() || msg.to_ids.contains(&GCS_ID) || desc.is_gcs() {
+                msgs.push(msg);
+                continue;
+            }
+            let mid = self.next_mid;
+            self.next_mid = self.next_mid.wrapping_add(1);
+            let msg = Msg {
+                sender: msg.sender,
+                to_ids: msg.to_ids,
+                body: MsgBody::Reliable(mid, Box::new(msg.body)),
+            };
+            self.pending.insert(mid, Pending {
+                msg: msg.clone(),
+                retransmits: 0,
+                timeout: self.retransmit_timeout,
+                next_t: now + self.retransmit_timeout,
+            });
+            msgs.push(msg);
+        }
+        for id in rm {
+            for pd in self.pending.values_mut() {
+                pd.msg.to_ids.retain(|rid| rid != id);
+            }
+        }
+        msgs.append(&mut self.retransmit(desc, now));
+        msgs
+    }
+
+    fn remove_receiver(&mut self, mid: u32, rid: u32) {
+        if let Some(pd) = self.pending.get_mut(&mid) {
+            pd.msg.to_ids.retain(|id| *id != rid);
+        }
+    }
+
+    // the sender is refreshed, since receivers also learn the position of this node from the message
+    fn retransmit(&mut self, desc: &NodeDesc, now: Instant) -> Vec<Msg> {
+        let mut msgs: Vec<Msg> = vec![];
+        let max_retransmits = self.max_retransmits;
+        self.pending.retain(|mid, pd| {
+            if pd.msg.to_ids.is_empty() {
+                return false;  // acknowledged by all receivers
+            }
+            if now < pd.next_t {
+                return true;
+            }
+            if pd.retransmits >= max_retransmits {
+                println!("uav {} gives up delivering message {} to {:?}", desc.get_id(), mid, pd.msg.to_ids);
+                return false;
+            }
+            pd.retransmits += 1;
+            pd.timeout *= 2;
+            pd.next_t = now + pd.timeout;
+            pd.msg.sender = desc.clone();
+            msgs.push(pd.msg.clone());
+            true
+        });
+        msgs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kinetics::{PosVec, Velocity};
+    use crate::control::msg::Target;
+
+    fn desc_of(id: u32) -> NodeDesc {
+        NodeDesc { nid: vec![id], ..NodeDesc::get_gcs_desc() }
+    }
+
+    fn msg_of(id: u32, to_ids: Vec<u32>, body: MsgBody) -> Msg {
+        Msg { sender: desc_of(id), to_ids, body }
+    }
+
+    fn mid_of(msg: &Msg) -> u32 {
+        match msg.body {
+            MsgBody::Reliable(mid, _) => mid,
+            _ => panic!("not reliable: {:?}", msg.body),
+        }
+    }
+
+    #[test]
+    fn only_events_between_uavs_are_wrapped() {
+        let mut dlv = Delivery::new();
+        let target = Target { tid: 0, seq: 0, p: PosVec::zero(), v: Velocity::zero() };
+        let msgs = dlv.send(&desc_of(1), vec![
+            msg_of(1, vec![2], MsgBody::Leave),
+            msg_of(1, vec![2], MsgBody::Target(target)),
+            msg_of(1, vec![], MsgBody::Empty),
+            msg_of(1, vec![GCS_ID], MsgBody::TaskCancelled(0)),
+        ], &[], Instant::now());
+        assert_eq!(msgs.len(), 4);
+        assert!(matches!(&msgs[0].body, MsgBody::Reliable(_, body) if matches!(**body, MsgBody::Leave)));
+        assert!(matches!(msgs[1].body, MsgBody::Target(_)));
+        assert!(matches!(msgs[2].body, MsgBody::Empty));
+        assert!(matches!(msgs[3].body, MsgBody::TaskCancelled(0)));
+    }
+
+    #[test]
+    fn duplicates_are_acked_but_dropped() {
+        let mut dlv = Delivery::new();
+        let now = Instant::now();
+        let msg = msg_of(1, vec![2], MsgBody::Reliable(7, Box::new(MsgBody::Leave)));
+        let (msgs, acks) = dlv.receive(&desc_of(2), &vec![&msg, &msg], now);
+        assert_eq!(msgs.len(), 1);
+        assert!(matches!(msgs[0].body, MsgBody::Leave));
+        assert_eq!(acks.len(), 2);
+        assert!(acks.iter().all(|a| a.to_ids == vec![1] && matches!(a.body, MsgBody::Ack(7))));
+        // a retransmission later on is still a duplicate
+        let (msgs, acks) = dlv.receive(&desc_of(2), &vec![&msg], now + Duration::from_secs(1));
+        assert!(msgs.is_empty());
+        assert_eq!(acks.len(), 1);
+        // the same id from another sender is another message
+        let other = msg_of(3, vec![2], MsgBody::Reliable(7, Box::new(MsgBody::Leave)));
+        let (msgs, _) = dlv.receive(&desc_of(2), &vec![&other], now);
+        assert_eq!(msgs.len(), 1);
+    }
+
+    #[test]
+    fn retransmissions_back_off_and_give_up() {
+        let mut dlv = Delivery::new();
+        let t0 = Instant::now();
+        let msgs = dlv.send(&desc_of(1), vec![msg_of(1, vec![2], MsgBody::Leave)], &[], t0);
+        let mid = mid_of(&msgs[0]);
+        let mut sent_t: Vec<Duration> = vec![];
+        let mut t = Duration::ZERO;
+        while t < Duration::from_secs(10) {
+            t += Duration::from_millis(50);
+            for msg in dlv.send(&desc_of(1), vec![], &[], t0 + t) {
+                assert_eq!(mid_of(&msg), mid);
+                sent_t.push(t);
+            }
+        }
+        let ms = |ms: u64| Duration::from_millis(ms);
+        assert_eq!(sent_t, vec![ms(300), ms(900), ms(2100), ms(4500)]);
+        assert!(dlv.pending.is_empty());
+    }
+
+    #[test]
+    fn acks_stop_retransmissions() {
+        let mut dlv = Delivery::new();
+        let t0 = Instant::now();
+        let msgs = dlv.send(&desc_of(1), vec![msg_of(1, vec![2, 3], MsgBody::CancelTask(0))], &[], t0);
+        let mid = mid_of(&msgs[0]);
+        let ack = msg_of(2, vec![1], MsgBody::Ack(mid));
+        let (msgs, acks) = dlv.receive(&desc_of(1), &vec![&ack], t0);
+        assert!(msgs.is_empty() && acks.is_empty());
+        // only the receiver not acknowledging yet is sent to again
+        let msgs = dlv.send(&desc_of(1), vec![], &[], t0 + Duration::from_millis(300));
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0].to_ids, vec![3]);
+        let ack = msg_of(3, vec![1], MsgBody::Ack(mid));
+        dlv.receive(&desc_of(1), &vec![&ack], t0);
+        assert!(dlv.send(&desc_of(1), vec![], &[], t0 + Duration::from_secs(10)).is_empty());
+        assert!(dlv.pending.is_empty());
+    }
+
+    #[test]
+    fn lost_contacts_are_not_waited_for() {
+        let mut dlv = Delivery::new();
+        let t0 = Instant::now();
+        dlv.send(&desc_of(1), vec![msg_of(1, vec![2], MsgBody::Leave)], &[], t0);
+        assert!(dlv.send(&desc_of(1), vec![], &[2], t0 + Duration::from_millis(300)).is_empty());
+        assert!(dlv.pending.is_empty());
+    }
+}
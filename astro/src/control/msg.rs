@@ -1,4 +1,4 @@
-use std::{option::Option, time::Duration};
+use std::{f32::consts::PI, option::Option, time::Duration};
 
 use serde::{Deserialize, Serialize};
 
@@ -8,6 +8,11 @@ use super::{PosVec, Velocity};
 // special id of ground control station
 pub const GCS_ID: u32 = u32::max_value();
 
+const ARC_COLLINEAR_EPS: f32 = 1e-8;  // relative, below which the points of an arc are taken as collinear
+const CUBIC_LENGTH_INTERVALS: u32 = 16;
+const CUBIC_SPLIT_ITERATIONS: u32 = 24;
+const DEFAULT_SWEEP_SPEED: f32 = 1.0;  // m/s
+
 // the node id of a uav in a tree structure.
 // it is: id(top) -> id -> ... -> id(this)
 pub type Nid = Vec<u32>;
@@ -52,6 +57,14 @@ pub struct NodeDesc {
     pub v: Velocity,
     pub swm: u32,  // the size of the swarm, down-flowing data
     pub tsk: Option<u32>,  // current task id of the node, down-flowing data
+    pub chd: u32,  // the number of children of the node
+    pub suc: Option<u32>,  // designated successor of the root node, down-flowing data
+    #[serde(default)]
+    pub clk: Option<Duration>,  // choreography time of the node, down-flowing data
+    #[serde(default)]
+    pub asg: Option<Vec<(u32, u32)>>,  // (child id, task id) if children are partitioned among tasks, down-flowing data
+    #[serde(default)]
+    pub tgt: Option<Target>,  // target of the tracking task of the node, as of now, down-flowing data
 }
 
 impl NodeDesc {
@@ -64,6 +77,9 @@ impl NodeDesc {
     #[inline]
     pub fn is_root_node(&self) -> bool { is_root_node(&self.nid) }
 
+    #[inline]
+    pub fn get_depth(&self) -> u32 { self.nid.len() as u32 }  // root node has depth 1
+
     #[inline]
     pub fn get_parent_id(&self) -> Option<u32> { parent_id_of(&self.nid) }
 
@@ -93,6 +109,15 @@ impl NodeDesc {
         }
     }
 
+    // task of the given child, which is the task of this node unless this node partitions its children.
+    // a child not listed in the partition is free.
+    pub fn get_child_task_id(&self, cid: u32) -> Option<u32> {
+        match &self.asg {
+            None => self.tsk,
+            Some(asg) => asg.iter().find(|(id, _)| *id == cid).map(|(_, tid)| *tid),
+        }
+    }
+
     #[inline]
     pub fn is_gcs(&self) -> bool { id_of(&self.nid) == GCS_ID }
 
@@ -103,38 +128,185 @@ impl NodeDesc {
             v: Velocity::zero(),
             swm: 0,
             tsk: None,
+            chd: 0,
+            suc: None,
+            clk: None,
+            asg: None,
+            tgt: None,
         }
     }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct JoinAppl {
     pub dtl: NodeDetails,
     pub src_tree: u32,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct AssignChildAppl {
     pub cid: u32,
     pub dtl: NodeDetails,
 }
 
+// curved segment between two consecutive points of a line
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub enum Curve {
+    Arc { via: PosVec },  // circular arc passing through `via`, straight if the three points are collinear
+    Cubic { c1: PosVec, c2: PosVec },  // cubic bezier curve with two control points
+}
+
+// circle of an arc: centre, radius, unit vectors of its plane with e1 pointing to the start,
+// and the angle swept from the start to the end
+struct ArcFrame {
+    centre: PosVec,
+    r: f32,
+    e1: PosVec,
+    e2: PosVec,
+    sweep: f32,
+}
+
+impl ArcFrame {
+    fn new(p0: &PosVec, via: &PosVec, p1: &PosVec) -> Option<ArcFrame> {
+        let (u, v) = (via - p0, p1 - p0);
+        let w = u.cross(&v);
+        let w_norm_sq = w.norm_square();
+        if w_norm_sq <= ARC_COLLINEAR_EPS * u.norm_square() * v.norm_square() {  // also a zero-length arc
+            return None;
+        }
+        let centre = p0 + (v.cross(&w) * u.norm_square() + w.cross(&u) * v.norm_square()) / (2.0 * w_norm_sq);
+        let r = distance(p0, &centre);
+        let e1 = (p0 - centre) / r;
+        let e2 = (w / w_norm_sq.sqrt()).cross(&e1);  // points run counterclockwise about w
+        let mut arc = ArcFrame { centre, r, e1, e2, sweep: 0.0 };
+        arc.sweep = arc.calc_angle(p1);
+        Some(arc)
+    }
+
+    // angle of a point on the circle from the start, in [0, 2pi)
+    fn calc_angle(&self, p: &PosVec) -> f32 {
+        let d = p - self.centre;
+        d.dot(&self.e2).atan2(d.dot(&self.e1)).rem_euclid(2.0 * PI)
+    }
+
+    fn calc_point(&self, angle: f32) -> PosVec {
+        self.centre + (self.e1 * angle.cos() + self.e2 * angle.sin()) * self.r
+    }
+}
+
+// cubic bezier curve from p0 to p3
+fn calc_cubic_point(p: [&PosVec; 4], t: f32) -> PosVec {
+    let s = 1.0 - t;
+    p[0] * (s * s * s) + p[1] * (3.0 * s * s * t) + p[2] * (3.0 * s * t * t) + p[3] * (t * t * t)
+}
+
+fn calc_cubic_length(p: [&PosVec; 4], t_end: f32) -> f32 {
+    // gauss-legendre quadrature of the speed over equal intervals, exact to float precision for smooth curves
+    const NODES: [(f32, f32); 5] = [
+        (0.0, 0.568_888_9),
+        (-0.538_469_3, 0.478_628_7), (0.538_469_3, 0.478_628_7),
+        (-0.906_179_8, 0.236_926_9), (0.906_179_8, 0.236_926_9),
+    ];
+    let speed = |t: f32| {
+        let s = 1.0 - t;
+        ((p[1] - p[0]) * (3.0 * s * s) + (p[2] - p[1]) * (6.0 * s * t) + (p[3] - p[2]) * (3.0 * t * t)).norm()
+    };
+    let dt = t_end / CUBIC_LENGTH_INTERVALS as f32;
+    (0..CUBIC_LENGTH_INTERVALS).map(|i| {
+        let t_mid = (i as f32 + 0.5) * dt;
+        NODES.iter().map(|(x, w)| w * speed(t_mid + x * dt / 2.0)).sum::<f32>() * dt / 2.0
+    }).sum()
+}
+
+impl Curve {
+    pub fn calc_length(&self, p0: &PosVec, p1: &PosVec) -> f32 {
+        match self {
+            Curve::Arc { via } => match ArcFrame::new(p0, via, p1) {
+                Some(arc) => arc.r * arc.sweep,
+                None => distance(p0, p1),
+            },
+            Curve::Cubic { c1, c2 } => calc_cubic_length([p0, c1, c2, p1], 1.0),
+        }
+    }
+
+    // split the curve at the given ratio of its length.
+    // out: the breakpoint, curves of the two parts.
+    pub fn split(&self, p0: &PosVec, p1: &PosVec, ratio: f32) -> (PosVec, Option<Curve>, Option<Curve>) {
+        match self {
+            Curve::Arc { via } => match ArcFrame::new(p0, via, p1) {
+                Some(arc) => {
+                    let angle = arc.sweep * ratio;
+                    let via1 = arc.calc_point(angle / 2.0);
+                    let via2 = arc.calc_point((angle + arc.sweep) / 2.0);
+                    (arc.calc_point(angle), Some(Curve::Arc { via: via1 }), Some(Curve::Arc { via: via2 }))
+                },
+                None => (p0 * (1.0 - ratio) + p1 * ratio, None, None),
+            },
+            Curve::Cubic { c1, c2 } => {
+                let p = [p0, c1, c2, p1];
+                let len = calc_cubic_length(p, 1.0) * ratio;
+                let (mut t_lo, mut t_hi) = (0.0, 1.0);
+                for _ in 0..CUBIC_SPLIT_ITERATIONS {  // bisection on the length from the start
+                    let t = (t_lo + t_hi) / 2.0;
+                    if calc_cubic_length(p, t) < len { t_lo = t; } else { t_hi = t; }
+                }
+                let t = (t_lo + t_hi) / 2.0;
+                // de casteljau subdivision
+                let lerp = |a: &PosVec, b: &PosVec| a * (1.0 - t) + b * t;
+                let (p01, p12, p23) = (lerp(p0, c1), lerp(c1, c2), lerp(c2, p1));
+                let (p012, p123) = (lerp(&p01, &p12), lerp(&p12, &p23));
+                let breakpoint = calc_cubic_point(p, t);
+                (breakpoint, Some(Curve::Cubic { c1: p01, c2: p012 }), Some(Curve::Cubic { c1: p123, c2: p23 }))
+            },
+        }
+    }
+
+    pub fn map_points(&mut self, f: impl Fn(&PosVec) -> PosVec) {
+        match self {
+            Curve::Arc { via } => { *via = f(via); },
+            Curve::Cubic { c1, c2 } => {
+                *c1 = f(c1);
+                *c2 = f(c2);
+            },
+        }
+    }
+}
+
 #[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct Line {
     pub points: Vec<PosVec>,
+    #[serde(default)]
+    pub curves: Vec<Option<Curve>>,  // curve of the segment after each point, straight if None or missing
     pub start: bool,  // whether there must be an uav at the start point of the line
     pub end: bool,  // whether there must be an uav at the end point of the line
 }
 
 impl Line {
-    pub fn calc_length(&self) -> f32 {
-        assert!(2 <= self.points.len());
+    // curve of the segment from point i to point i + 1
+    pub fn get_curve(&self, i: usize) -> Option<&Curve> {
+        self.curves.get(i).and_then(|c| c.as_ref())
+    }
+
+    pub fn calc_segment_length(&self, i: usize) -> f32 {
+        let (p0, p1) = (&self.points[i], &self.points[i + 1]);
+        match self.get_curve(i) {
+            Some(curve) => curve.calc_length(p0, p1),
+            None => distance(p0, p1),
+        }
+    }
+
+    pub fn calc_length(&self) -> Result<f32, TaskError> {
+        if self.points.len() < 2 {
+            return Err(TaskError::TooFewPoints);
+        }
         let mut len: f32 = 0.0;
         for i in 1..self.points.len() {
-            len += distance(&self.points[i], &self.points[i - 1]);
+            len += self.calc_segment_length(i - 1);
         }
-        assert!(0.0 < len);
-        len
+        if len <= 0.0 {
+            return Err(TaskError::ZeroLength);
+        }
+        Ok(len)
     }
 
     pub fn num_end_points(&self) -> u32 {
@@ -152,18 +324,650 @@ impl Line {
     }
 }
 
+// what to do with the points of a point-set task when there are fewer uavs than points
+#[derive(Clone, Copy, PartialEq, Default, Deserialize, Serialize, Debug)]
+pub enum PointPolicy {
+    #[default]
+    Fail,  // the task fails
+    Subsample,  // the points are subsampled evenly over space
+}
+
+// a shape following the previous one in a choreography.
 #[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct Keyframe {
+    pub lines: Vec<Line>,
+    pub transition: Duration,  // time to move from the previous shape to this shape
+    pub hold: Duration,  // time to hold this shape
+}
+
+// a point on the path of a moving formation, the frame of the shape moves straight to it at the speed.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct Waypoint {
+    pub offset: PosVec,  // displacement of the shape frame from the initial shape, in world coordinates
+    pub speed: f32,  // m/s
+}
+
+// placement of a shape defined in its own frame: p_world = anchor + R(yaw, pitch, roll) * (scale * p),
+// where the shape is rotated by roll about x, then pitch about y, then yaw about z.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct Transform {
+    pub anchor: PosVec,
+    #[serde(default)]
+    pub relative: bool,  // whether the anchor is relative to the current position of the root node
+    #[serde(default)]
+    pub yaw: f32,  // rad
+    #[serde(default)]
+    pub pitch: f32,  // rad
+    #[serde(default)]
+    pub roll: f32,  // rad
+    #[serde(default = "Transform::default_scale")]
+    pub scale: f32,
+}
+
+impl Transform {
+    fn default_scale() -> f32 { 1.0 }
+
+    pub fn apply(&self, p: &PosVec) -> PosVec {
+        let p = p * self.scale;
+        let (sin, cos) = self.roll.sin_cos();
+        let p = PosVec { x: p.x, y: cos * p.y - sin * p.z, z: sin * p.y + cos * p.z };
+        let (sin, cos) = self.pitch.sin_cos();
+        let p = PosVec { x: cos * p.x + sin * p.z, y: p.y, z: -sin * p.x + cos * p.z };
+        let (sin, cos) = self.yaw.sin_cos();
+        let p = PosVec { x: cos * p.x - sin * p.y, y: sin * p.x + cos * p.y, z: p.z };
+        self.anchor + p
+    }
+
+    // a similarity transform keeps arcs circular
+    fn apply_to_lines(&self, lines: &mut [Line]) {
+        for line in lines {
+            for p in &mut line.points {
+                *p = self.apply(p);
+            }
+            for curve in line.curves.iter_mut().flatten() {
+                curve.map_points(|p| self.apply(p));
+            }
+        }
+    }
+}
+
+// a straight pass over the area of a coverage task, swept by one uav in either direction
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct Lane {
+    pub id: u32,  // unique in the task, to track which lanes have been covered
+    pub a: PosVec,
+    pub b: PosVec,
+}
+
+impl Lane {
+    pub fn calc_length(&self) -> f32 {
+        distance(&self.a, &self.b)
+    }
+}
+
+// a polygon area to be swept at an altitude, in lanes one sensor footprint wide.
+// the lanes are divided among the subswarms in bands, each uav flies a lawnmower sweep over its own lanes.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct Coverage {
+    pub area: Vec<PosVec>,  // vertices of the polygon in order, z ignored
+    pub altitude: f32,  // m
+    pub width: f32,  // m, width of the sensor footprint across a lane
+    #[serde(default = "Coverage::default_speed")]
+    pub speed: f32,  // m/s, along a lane
+    #[serde(default)]
+    pub lanes: Vec<Lane>,  // lanes of the area, generated by the top node if empty
+}
+
+impl Coverage {
+    fn default_speed() -> f32 { DEFAULT_SWEEP_SPEED }
+
+    // cut the area into lanes parallel to the longer side of its bounding box, evenly spaced no wider than
+    // the footprint. a lane crossing a concave area more than once is split at the gaps.
+    // lanes are in order of their offset, so consecutive lanes make a band of the area.
+    pub fn generate_lanes(&self) -> Vec<Lane> {
+        let min_x = self.area.iter().map(|p| p.x).fold(f32::INFINITY, f32::min);
+        let max_x = self.area.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max);
+        let min_y = self.area.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
+        let max_y = self.area.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max);
+        let along_x = max_x - min_x >= max_y - min_y;
+        // u along the lanes, v across them
+        let to_uv = |p: &PosVec| if along_x { (p.x, p.y) } else { (p.y, p.x) };
+        let from_uv = |u: f32, v: f32| if along_x {
+            PosVec { x: u, y: v, z: self.altitude }
+        } else {
+            PosVec { x: v, y: u, z: self.altitude }
+        };
+        let (v_min, v_max) = if along_x { (min_y, max_y) } else { (min_x, max_x) };
+        let num = ((v_max - v_min) / self.width).ceil().max(1.0) as u32;
+        let pitch = (v_max - v_min) / num as f32;
+        let mut lanes: Vec<Lane> = vec![];
+        for k in 0..num {
+            let v = v_min + (k as f32 + 0.5) * pitch;
+            let mut crossings: Vec<f32> = vec![];
+            for (i, p) in self.area.iter().enumerate() {
+                let ((u0, v0), (u1, v1)) = (to_uv(p), to_uv(&self.area[(i + 1) % self.area.len()]));
+                if (v0 <= v) != (v1 <= v) {
+                    crossings.push(u0 + (v - v0) / (v1 - v0) * (u1 - u0));
+                }
+            }
+            crossings.sort_by(f32::total_cmp);
+            for pair in crossings.chunks_exact(2) {
+                if pair[1] > pair[0] {
+                    lanes.push(Lane { id: lanes.len() as u32, a: from_uv(pair[0], v), b: from_uv(pair[1], v) });
+                }
+            }
+        }
+        lanes
+    }
+
+    // shoelace formula over the horizontal projection
+    pub fn calc_area(&self) -> f32 {
+        let n = self.area.len();
+        (0..n).map(|i| {
+            let (p, q) = (&self.area[i], &self.area[(i + 1) % n]);
+            p.x * q.y - q.x * p.y
+        }).sum::<f32>().abs() / 2.0
+    }
+}
+
+// a moving point of interest of a tracking task, sent by gcs and passed down the tree
+#[derive(Clone, Copy, Deserialize, Serialize, Debug)]
+pub struct Target {
+    pub tid: u32,
+    pub seq: u32,  // count of updates by gcs, an update older than the last one is ignored
+    pub p: PosVec,
+    pub v: Velocity,
+}
+
+// the shapes of a tracking task are given relative to the target, which is at their origin.
+// if `heading` is set, they turn with the heading of the target, their x axis pointing forward.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct Tracking {
+    pub target: PosVec,  // position of the target when the task is sent
+    #[serde(default)]
+    pub heading: bool,
+}
+
+// what the root node does when the formation error exceeds the threshold while keeping station
+#[derive(Clone, Copy, PartialEq, Default, Deserialize, Serialize, Debug)]
+pub enum StationPolicy {
+    #[default]
+    Reconverge,  // the uavs fly back to their positions in the current division
+    Reassign,  // the task is divided again, from the current positions of the uavs, until the formation is restored
+    Fail,  // the task fails
+}
+
+// the formation is kept after the task has succeeded, until another task takes over or the task is cancelled
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct StationKeeping {
+    pub threshold: f32,  // m, root mean square of the position errors of the uavs
+    #[serde(default)]
+    pub policy: StationPolicy,
+}
+
+#[derive(Clone, Default, Deserialize, Serialize, Debug)]
 pub struct Task {
     pub id: u32,
     pub lines: Vec<Line>,
     pub duration: Duration,
     pub comm_point: Option<PosVec>,
+    #[serde(default)]
+    pub priority: u32,  // a task of higher priority preempts a running task of lower priority
+    #[serde(default)]
+    pub requeue: bool,  // whether to put the task back into the queue after being preempted
+    #[serde(default)]
+    pub div: u32,  // revision of the division that produced this subtask, 0 for a gcs task
+    #[serde(default)]
+    pub uavs: u32,  // number of uavs requested, 0 for the whole swarm
+    #[serde(default)]
+    pub subtree: Option<u32>,  // id of the child of the root node whose subswarm runs the task
+    #[serde(default)]
+    pub keyframes: Vec<Keyframe>,  // shapes played after `lines` has been formed and held for `duration`
+    #[serde(default)]
+    pub points: Vec<PosVec>,  // formation given as a set of points, instead of `lines`
+    #[serde(default)]
+    pub point_policy: PointPolicy,
+    #[serde(default)]
+    pub park: bool,  // the subswarm has no part in the shape, its uavs hold their positions
+    #[serde(default)]
+    pub reach_timeout: Option<Duration>,  // time for each uav to reach its position, default by configuration
+    #[serde(default)]
+    pub task_timeout: Option<Duration>,  // time for the whole task to succeed, default by configuration unless open-ended
+    #[serde(default)]
+    pub transform: Option<Transform>,  // placement of the shapes, which are in world coordinates if None
+    #[serde(default)]
+    pub path: Vec<Waypoint>,  // path of the formation after the initial shape has been formed
+    #[serde(default)]
+    pub tolerance: Option<f32>,  // m, how far an uav may fall behind the moving formation, default by executor
+    #[serde(default)]
+    pub coverage: Option<Coverage>,  // area to sweep, instead of a formation
+    #[serde(default)]
+    pub tracking: Option<Tracking>,  // target the formation is kept around, updated without a new task
+    #[serde(default)]
+    pub station: Option<StationKeeping>,  // the task ends on success if None
+}
+
+impl Task {
+    // a partial task runs on the subswarm of one child of the root node,
+    // concurrently with other partial tasks and with a task of the whole swarm.
+    pub fn is_partial(&self) -> bool {
+        self.uavs > 0 || self.subtree.is_some()
+    }
+
+    // the running time of an open-ended task is set by its own motion, sweep or target, or by gcs cancelling it,
+    // so a task deadline is only given by gcs.
+    pub fn is_open_ended(&self) -> bool {
+        !self.keyframes.is_empty() || !self.path.is_empty() || self.coverage.is_some() || self.tracking.is_some()
+    }
+
+    // place all shapes in the world by the transform, the task then has no transform.
+    // a relative anchor must have been resolved by the root node beforehand, otherwise the task is left as it is.
+    pub fn apply_transform(&mut self) -> Result<(), TaskError> {
+        if self.transform.as_ref().is_some_and(|tf| tf.relative) {
+            return Err(TaskError::UnresolvedAnchor);
+        }
+        let tf = match self.transform.take() {
+            Some(tf) => tf,
+            None => { return Ok(()); },
+        };
+        tf.apply_to_lines(&mut self.lines);
+        for kf in &mut self.keyframes {
+            tf.apply_to_lines(&mut kf.lines);
+        }
+        for p in &mut self.points {
+            *p = tf.apply(p);
+        }
+        // an area stays horizontal at its altitude, only its horizontal placement is transformed
+        if let Some(cov) = self.coverage.as_mut() {
+            for p in cov.area.iter_mut().chain(cov.lanes.iter_mut().flat_map(|l| [&mut l.a, &mut l.b])) {
+                *p = PosVec { z: p.z, ..tf.apply(p) };
+            }
+        }
+        Ok(())
+    }
+
+    // check the task before it is dispatched, so that a bad task is rejected
+    // instead of failing deep inside the division
+    pub fn validate(&self, constraints: &TaskConstraints) -> Result<(), TaskError> {
+        if !self.is_finite() {
+            return Err(TaskError::NonFinite);
+        }
+        if self.transform.as_ref().is_some_and(|tf| tf.scale <= 0.0) {
+            return Err(TaskError::InvalidScale);
+        }
+        if self.path.iter().any(|wp| wp.speed <= 0.0) {
+            return Err(TaskError::InvalidSpeed);
+        }
+        // only the size of the shapes is checked, a relative anchor does not change it
+        let mut task = self.clone();
+        if let Some(tf) = task.transform.as_mut() {
+            tf.relative = false;
+        }
+        task.apply_transform()?;
+        if let Some(cov) = &task.coverage {
+            if !task.lines.is_empty() || !task.points.is_empty() {
+                return Err(TaskError::MixedShapes);
+            }
+            if cov.width <= 0.0 {
+                return Err(TaskError::InvalidWidth);
+            }
+            if cov.speed <= 0.0 {
+                return Err(TaskError::InvalidSpeed);
+            }
+            if cov.area.len() < 3 {
+                return Err(TaskError::TooFewPoints);
+            }
+            if cov.calc_area() <= 0.0 {
+                return Err(TaskError::ZeroLength);
+            }
+        } else if !task.park {
+            if task.points.is_empty() {
+                validate_lines(&task.lines, constraints.min_spacing)?;
+            } else if !task.lines.is_empty() {
+                return Err(TaskError::MixedShapes);
+            } else if task.point_policy == PointPolicy::Fail {  // subsampled points are spread out by the division
+                for (i, p) in task.points.iter().enumerate() {
+                    if task.points[i + 1..].iter().any(|q| distance(p, q) < constraints.min_spacing) {
+                        return Err(TaskError::TooDense);
+                    }
+                }
+            }
+        }
+        for kf in &task.keyframes {
+            validate_lines(&kf.lines, constraints.min_spacing)?;
+        }
+        if let Some(swarm_size) = constraints.swarm_size {
+            // a partial task runs without the root node
+            let available = if self.is_partial() { swarm_size.saturating_sub(1) } else { swarm_size };
+            let least_uavs = self.num_least_uavs();
+            if available < least_uavs.max(self.uavs) {
+                return Err(TaskError::TooFewUavs);
+            }
+            let uavs = if self.uavs > 0 { self.uavs } else if self.is_partial() { least_uavs } else { available };
+            if !task.park && task.points.is_empty() && task.coverage.is_none() {
+                check_line_spacing(&task.lines, uavs, constraints.min_spacing)?;
+            }
+            for kf in &task.keyframes {
+                check_line_spacing(&kf.lines, uavs, constraints.min_spacing)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_finite(&self) -> bool {
+        let is_finite = |p: &PosVec| p.x.is_finite() && p.y.is_finite() && p.z.is_finite();
+        let are_lines_finite = |lines: &Vec<Line>| lines.iter().all(|l| {
+            l.points.iter().all(is_finite) && l.curves.iter().flatten().all(|c| match c {
+                Curve::Arc { via } => is_finite(via),
+                Curve::Cubic { c1, c2 } => is_finite(c1) && is_finite(c2),
+            })
+        });
+        are_lines_finite(&self.lines)
+            && self.keyframes.iter().all(|kf| are_lines_finite(&kf.lines))
+            && self.points.iter().all(is_finite)
+            && self.comm_point.as_ref().is_none_or(is_finite)
+            && self.transform.as_ref().is_none_or(|tf| {
+                is_finite(&tf.anchor) && [tf.yaw, tf.pitch, tf.roll, tf.scale].iter().all(|a| a.is_finite())
+            })
+            && self.path.iter().all(|wp| is_finite(&wp.offset) && wp.speed.is_finite())
+            && self.tolerance.is_none_or(|t| t.is_finite())
+            && self.tracking.as_ref().is_none_or(|trk| is_finite(&trk.target))
+            && self.station.as_ref().is_none_or(|stn| stn.threshold.is_finite())
+            && self.coverage.as_ref().is_none_or(|cov| {
+                cov.area.iter().all(is_finite) && cov.lanes.iter().all(|l| is_finite(&l.a) && is_finite(&l.b))
+                    && [cov.altitude, cov.width, cov.speed].iter().all(|a| a.is_finite())
+            })
+    }
+
+    // least number of uavs to form every shape of this task
+    pub fn num_least_uavs(&self) -> u32 {
+        let least_uavs = |lines: &Vec<Line>| lines.iter().map(|l| l.num_least_uavs()).sum::<u32>();
+        let least_uavs_init = if self.park || self.coverage.is_some() {
+            1
+        } else if self.points.is_empty() {
+            least_uavs(&self.lines)
+        } else {
+            match self.point_policy {
+                PointPolicy::Fail => self.points.len() as u32,
+                PointPolicy::Subsample => 1,
+            }
+        };
+        self.keyframes.iter().map(|kf| least_uavs(&kf.lines)).fold(least_uavs_init, u32::max)
+    }
+}
+
+// every line of a shape can be formed by uavs at least `min_spacing` apart
+fn validate_lines(lines: &[Line], min_spacing: f32) -> Result<(), TaskError> {
+    if lines.is_empty() {
+        return Err(TaskError::NoLines);
+    }
+    for line in lines {
+        let len = line.calc_length()?;
+        if line.start && line.end && len < min_spacing {
+            return Err(TaskError::TooDense);
+        }
+    }
+    Ok(())
+}
+
+// spacing of the uavs when they are spread evenly over all lines of a shape,
+// each end point taking an uav that is shared by no segment on its outer side
+fn check_line_spacing(lines: &[Line], uavs: u32, min_spacing: f32) -> Result<(), TaskError> {
+    let len: f32 = lines.iter().filter_map(|l| l.calc_length().ok()).sum();
+    let end_points: u32 = lines.iter().map(|l| l.num_end_points()).sum();
+    let gaps = uavs as f32 - end_points as f32 / 2.0;
+    if gaps > 0.0 && len / gaps < min_spacing {
+        return Err(TaskError::TooDense);
+    }
+    Ok(())
+}
+
+// limits a task is checked against before it is dispatched
+pub struct TaskConstraints {
+    pub min_spacing: f32,  // m, least distance between two uavs of a shape
+    pub swarm_size: Option<u32>,  // number of uavs of the swarm, unchecked if unknown
+}
+
+#[derive(Clone, Copy, PartialEq, Deserialize, Serialize, Debug)]
+pub enum TaskOutcome {
+    Success,
+    Failure,
+    Preempted,
+    Cancelled,
+    Rejected,  // the task is invalid and has never been dispatched
+}
+
+// why a task cannot be divided into subtasks
+#[derive(Clone, Copy, PartialEq, Deserialize, Serialize, Debug)]
+pub enum TaskError {
+    NoLines,  // the task has no line to form
+    TooFewPoints,  // a line has less than two points
+    ZeroLength,  // a line has no length
+    MixedShapes,  // the task has both lines and points
+    InvalidSpeed,  // a waypoint has no positive speed
+    TooFewUavs,  // the subswarm is too small for the lines of the task
+    OutOfCommRange,  // own position is out of range of the communication point given by the parent
+    NonFinite,  // a coordinate, angle or speed of the task is infinite or nan
+    InvalidScale,  // the transform has no positive scale
+    TooDense,  // uavs of a shape would be closer than the least spacing
+    InvalidWidth,  // the sensor footprint of a coverage has no positive width
+    UnresolvedAnchor,  // the transform is still relative to the root node
+    Indivisible,  // the shapes cannot be split consistently among the uavs, e.g. being degenerate
+}
+
+// why a node has failed its task
+#[derive(Clone, Copy, PartialEq, Deserialize, Serialize, Debug)]
+pub enum FailureReason {
+    ReachTimeout,  // an uav has not reached its position in time
+    TaskTimeout,  // the task has not succeeded in time
+    FormationLost,  // an uav has fallen out of the moving formation
+    Division(TaskError),  // the task cannot be divided
+    Invalid(TaskError),  // the task has been rejected by the root node
+    FormationDegraded,  // the formation error has exceeded the threshold while keeping station
+}
+
+// outcome of a task, reported by the root node to gcs when the task ends.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct TaskReport {
+    pub tid: u32,
+    pub outcome: TaskOutcome,
+    pub uavs: u32,  // size of the swarm when the task ended
+    pub elapsed: Duration,  // from the start of the task by the root node to the end of the task
+    #[serde(default)]
+    pub reason: Option<FailureReason>,  // reason of failure, if known
+    #[serde(default)]
+    pub coverage: Option<f32>,  // fraction of the area covered, for a coverage task
+}
+
+// change of the formation of a task keeping station, reported by the root node to gcs
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct FormationReport {
+    pub tid: u32,
+    pub degraded: bool,  // whether the formation error has gone above the threshold, or back below it
+    pub rms: f32,  // m, root mean square of the position errors
+    pub max: f32,  // m, largest position error
+}
+
+// position errors of the uavs of a subswarm from their targets in the task
+#[derive(Clone, Copy, Default, Deserialize, Serialize, Debug)]
+pub struct PosError {
+    pub count: u32,  // number of uavs
+    pub sum_sq: f32,  // m^2, sum of the squared errors
+    pub max: f32,  // m
+}
+
+impl PosError {
+    pub fn of_uav(err: f32) -> PosError {
+        PosError { count: 1, sum_sq: err * err, max: err }
+    }
+
+    pub fn merge(&mut self, other: &PosError) {
+        self.count += other.count;
+        self.sum_sq += other.sum_sq;
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn calc_rms(&self) -> f32 {
+        if self.count == 0 { 0.0 } else { (self.sum_sq / self.count as f32).sqrt() }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Deserialize, Serialize, Debug)]
+pub enum CensusMode {
+    Full,  // every node of the swarm is listed
+    Summary,  // only aggregate values of the swarm, for large swarms
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct CensusQuery {
+    pub qid: u32,  // query id
+    pub mode: CensusMode,
+    #[serde(default)]
+    pub page_size: u32,  // most entries of a full census in one reply to gcs, 0 means no paging
+    pub budget: Duration,  // how long the receiver may take to reply
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct CensusEntry {
+    pub nid: Nid,
+    pub p: PosVec,
+    pub v: Velocity,
+    pub state: SubswarmTaskState,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct CensusSummary {
+    pub count: u32,  // number of nodes
+    pub depth: u32,  // depth of the deepest node
+    pub p_mean: PosVec,
+    pub p_min: PosVec,  // corner of the bounding box
+    pub p_max: PosVec,  // corner of the bounding box
+    pub v_mean: Velocity,
+    pub in_task: u32,  // number of nodes having a task
+}
+
+impl CensusSummary {
+    pub fn from_entry(entry: &CensusEntry) -> CensusSummary {
+        CensusSummary {
+            count: 1,
+            depth: entry.nid.len() as u32,
+            p_mean: entry.p,
+            p_min: entry.p,
+            p_max: entry.p,
+            v_mean: entry.v,
+            in_task: match entry.state {
+                SubswarmTaskState::None => 0,
+                _ => 1,
+            },
+        }
+    }
+
+    pub fn merge(&mut self, other: &CensusSummary) {
+        let count = self.count + other.count;
+        let (w1, w2) = (self.count as f32 / count as f32, other.count as f32 / count as f32);
+        self.p_mean = self.p_mean * w1 + other.p_mean * w2;
+        self.v_mean = self.v_mean * w1 + other.v_mean * w2;
+        self.p_min = PosVec {
+            x: self.p_min.x.min(other.p_min.x),
+            y: self.p_min.y.min(other.p_min.y),
+            z: self.p_min.z.min(other.p_min.z),
+        };
+        self.p_max = PosVec {
+            x: self.p_max.x.max(other.p_max.x),
+            y: self.p_max.y.max(other.p_max.y),
+            z: self.p_max.z.max(other.p_max.z),
+        };
+        self.count = count;
+        self.depth = self.depth.max(other.depth);
+        self.in_task += other.in_task;
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub enum CensusData {
+    Full(Vec<CensusEntry>),
+    Summary(CensusSummary),
+}
+
+impl CensusData {
+    pub fn from_entry(mode: CensusMode, entry: CensusEntry) -> CensusData {
+        match mode {
+            CensusMode::Full => CensusData::Full(vec![entry]),
+            CensusMode::Summary => CensusData::Summary(CensusSummary::from_entry(&entry)),
+        }
+    }
+
+    pub fn to_summary(&self) -> CensusSummary {
+        match self {
+            CensusData::Summary(summary) => summary.clone(),
+            CensusData::Full(entries) => {
+                let mut summary = CensusSummary::from_entry(&entries[0]);
+                for entry in &entries[1..] {
+                    summary.merge(&CensusSummary::from_entry(entry));
+                }
+                summary
+            },
+        }
+    }
+
+    pub fn merge(&mut self, other: CensusData) {
+        match (&mut *self, other) {
+            (CensusData::Full(entries), CensusData::Full(mut others)) => entries.append(&mut others),
+            (CensusData::Summary(summary), other) => summary.merge(&other.to_summary()),
+            (_, other) => {  // full census with summary from a child
+                let mut summary = self.to_summary();
+                summary.merge(&other.to_summary());
+                *self = CensusData::Summary(summary);
+            },
+        }
+    }
+
+    // split a full census into pages of at most `page_size` entries
+    pub fn into_pages(self, page_size: u32) -> Vec<CensusData> {
+        match self {
+            CensusData::Full(entries) if page_size > 0 && entries.len() > page_size as usize => {
+                entries.chunks(page_size as usize).map(|c| CensusData::Full(c.to_vec())).collect()
+            },
+            data => vec![data],
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct CensusReply {
+    pub qid: u32,
+    pub data: CensusData,  // aggregate of the subswarm of the sender
+    #[serde(default)]
+    pub page: u32,  // index of this page, in the reply of root node to gcs
+    #[serde(default)]
+    pub pages: u32,  // number of pages, in the reply of root node to gcs
+}
+
+// copy of the tasks held by the root node, kept by its designated successor.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct TaskReplica {
+    pub task: Option<Task>,  // current task of the swarm
+    pub queue: Vec<Task>,  // queued tasks, in order
+    pub deferred: Vec<Task>,  // tasks waiting for the swarm to grow
+    pub old: Vec<u32>,  // ids of the tasks ended, cancelled or rejected, never to be accepted again
+}
+
+impl TaskReplica {
+    // what the replica is made of, which changes whenever a task is queued, started or ended.
+    // tasks are not modified once received, so their ids stand for them.
+    pub fn get_stamp(&self) -> Vec<u32> {
+        let mut stamp = vec![self.task.is_some() as u32, self.queue.len() as u32, self.deferred.len() as u32,
+            self.old.len() as u32];
+        stamp.extend(self.task.iter().chain(&self.queue).chain(&self.deferred).map(|t| t.id));
+        stamp
+    }
 }
 
 // usual transitions: None -> Recv -> Algn -> Allc -> Succ -> None.
 // other transitions: Recv/Algn/Allc/Succ -> Fail -> None,
 //                    Succ -> Allc,
-//                    Recv -> Allc (root node only)
+//                    Recv -> Allc (root node only),
+//                    Recv(id)/Algn(id)/Allc(id) -> Recv(id_new) (preemption by a task of higher priority)
 // for root node, reception of a gcs task message means task allocated to root node,
 // but the alignment not done yet, so the swarm is in Recv,
 // after alignment done, the swarm changes to Allc, skipping Algn.
@@ -197,6 +1001,15 @@ pub enum SubswarmTaskState {
 pub struct NodeDetails {
     pub subswarm: u32,  // the size of the subswarm, up-flowing data
     pub subswm_tsk: SubswarmTaskState,  // the task state of the subswarm, up-flowing data
+    pub div: u32,  // revision of the division of the subtask held by the top node, 0 if none, up-flowing data
+    #[serde(default)]
+    pub rsn: Option<FailureReason>,  // reason of the failure of the subswarm, up-flowing data
+    #[serde(default)]
+    pub ctr: Option<PosVec>,  // centroid of the subswarm, up-flowing data
+    #[serde(default)]
+    pub cov: Vec<u32>,  // lanes of the coverage task covered by the subswarm, up-flowing data
+    #[serde(default)]
+    pub err: Option<PosError>,  // position errors of the uavs of the subswarm in the task, up-flowing data
 }
 
 impl NodeDetails {
@@ -235,7 +1048,7 @@ impl NodeDetails {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 pub enum MsgBody {
     Empty,
     Connection(NodeDetails),  // sender keeps connection with receiver (parent and children)
@@ -244,15 +1057,39 @@ pub enum MsgBody {
     Accept,  // sender rejects the receiver as its child
     Reject,  // sender accepts the receiver as its child
     Leave,  // sender stops recognising the receiver as its parent
+    Reattach(JoinAppl),  // sender lost its parent, and wants to set the receiver (on same tree) as its new parent
 
     ChangeParent(u32),  // sender sets a third node (on same tree) as the receiver's new parent
     AssignChild(AssignChildAppl),  // sender sets a third node (on same tree) as the receiver's new child
+    ChildAssigned(u32),  // sender has taken a third node as its child, as requested by the receiver
+    ChildRefused(u32),  // sender has not taken a third node as its child, as requested by the receiver
 
     Task(Task),  // sender is gcs, or sender relays a gcs task to the receiver (sender's parent)
+    CancelTask(u32),  // sender is gcs, or sender relays a gcs task cancellation to the receiver (sender's parent)
+    Target(Target),  // sender is gcs, or sender relays a target update of a tracking task to the receiver (sender's parent)
+    TaskCancelled(u32),  // sender (root node) acknowledges the task cancellation to the receiver (gcs)
+    TaskReport(TaskReport),  // sender (root node) reports the outcome of a task to the receiver (gcs)
+    FormationReport(FormationReport),  // sender (root node) reports a change of the formation to the receiver (gcs)
+    CensusQuery(CensusQuery),  // sender is gcs, or sender relays the query up to its parent or down to its children
+    CensusReply(CensusReply),  // sender replies the census of its subswarm to its parent, or root node to gcs
+    Succession(TaskReplica),  // sender (root node) replicates its tasks to the receiver (designated successor)
     Subtask(Task),  // sender allocate a subtask to the receiver (sender's child)
+
+    Reliable(u32, Box<MsgBody>),  // sender sends an event message of the id, to be acknowledged by each receiver
+    Ack(u32),  // sender acknowledges the event message of the id from the receiver
+}
+
+impl MsgBody {
+    // one-shot events are delivered reliably between uavs.
+    // periodic and state carrying messages are sent again anyway, a stale copy of them must not arrive late.
+    pub fn is_event(&self) -> bool {
+        matches!(self, MsgBody::Join(_) | MsgBody::Accept | MsgBody::Reject | MsgBody::Leave | MsgBody::Reattach(_)
+            | MsgBody::ChangeParent(_) | MsgBody::AssignChild(_) | MsgBody::ChildAssigned(_) | MsgBody::ChildRefused(_)
+            | MsgBody::Task(_) | MsgBody::CancelTask(_))
+    }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct Msg {
     pub sender: NodeDesc,  // node description of message sender
     pub to_ids: Vec<u32>,  // target message receivers, None means broadcasting
@@ -267,4 +1104,52 @@ impl Msg {
             body: MsgBody::Empty,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coverage_of(area: &[(f32, f32)], width: f32) -> Coverage {
+        Coverage {
+            area: area.iter().map(|&(x, y)| PosVec { x, y, z: 0.0 }).collect(),
+            altitude: 10.0,
+            width,
+            speed: DEFAULT_SWEEP_SPEED,
+            lanes: vec![],
+        }
+    }
+
+    #[test]
+    fn lanes_run_along_the_longer_side() {
+        let cov = coverage_of(&[(0.0, 0.0), (20.0, 0.0), (20.0, 6.0), (0.0, 6.0)], 2.0);
+        let lanes = cov.generate_lanes();
+        assert_eq!(lanes.len(), 3);
+        for (k, lane) in lanes.iter().enumerate() {
+            assert_eq!(lane.id, k as u32);
+            assert_eq!(lane.a.y, lane.b.y);
+            assert_eq!(lane.a.y, 1.0 + 2.0 * k as f32);
+            assert_eq!(lane.calc_length(), 20.0);
+            assert_eq!(lane.a.z, 10.0);
+        }
+        let cov = coverage_of(&[(0.0, 0.0), (5.0, 0.0), (5.0, 30.0), (0.0, 30.0)], 2.0);
+        let lanes = cov.generate_lanes();
+        // no wider than the footprint: 3 lanes of pitch 5/3
+        assert_eq!(lanes.len(), 3);
+        assert!(lanes.iter().all(|l| l.a.x == l.b.x && l.calc_length() == 30.0));
+        assert_eq!(cov.calc_area(), 150.0);
+    }
+
+    #[test]
+    fn lanes_are_split_at_gaps_of_concave_areas() {
+        // a u shape open at the top: the upper lanes cross both arms
+        let cov = coverage_of(&[(0.0, 0.0), (30.0, 0.0), (30.0, 20.0), (20.0, 20.0), (20.0, 10.0), (10.0, 10.0),
+                                (10.0, 20.0), (0.0, 20.0)], 10.0);
+        let lanes = cov.generate_lanes();
+        assert_eq!(lanes.len(), 3);
+        assert_eq!(lanes[0].calc_length(), 30.0);
+        assert_eq!((lanes[1].a.x, lanes[1].b.x), (0.0, 10.0));
+        assert_eq!((lanes[2].a.x, lanes[2].b.x), (20.0, 30.0));
+        assert_eq!(cov.calc_area(), 500.0);
+    }
+}
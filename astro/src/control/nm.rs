@@ -5,9 +5,12 @@ use std::time::{Duration, Instant};
 
 use super::super::astroconf::AstroConf;
 use super::super::kinetics::{distance, PosVec, Velocity};
+use super::census::CensusCollector;
 use super::contacts::Contact;
-use super::msg::{root_nid, id_of, is_id_valid_descendant_of, parent_id_of, root_id_of, Nid};
-use super::msg::{NodeDesc, NodeDetails, JoinAppl, AssignChildAppl, Task, SubswarmTaskState, MsgBody, Msg};
+use super::msg::{root_nid, id_of, is_id_valid_descendant_of, parent_id_of, root_id_of, Nid, GCS_ID};
+use super::msg::{NodeDesc, NodeDetails, JoinAppl, AssignChildAppl, Task, TaskReplica, TaskOutcome, TaskReport};
+use super::msg::{FailureReason, FormationReport, PosError, StationPolicy, Target, TaskConstraints, TaskError};
+use super::msg::{CensusData, CensusEntry, CensusQuery, CensusReply, SubswarmTaskState, MsgBody, Msg};
 use super::tm::{ChildInfo, TaskManager};
 
 pub const DEFAULT_NODE_LOST_DURATION: Duration = Duration::from_secs(5);
@@ -18,6 +21,9 @@ pub const DEFAULT_STATE_MSG_DURATION: Duration = Duration::from_millis(100);
 pub const NEW_PARENT_FRESHNESS: Duration = Duration::from_millis(1000);
 pub const CHILD_ADDING_TIMESCALE: Duration = Duration::from_millis(300);
 pub const CHILD_ADDING_RATE_LIMIT: f32 = 0.5;
+pub const REBALANCING_TIMEOUT: Duration = Duration::from_millis(1500);
+pub const SUCCESSION_REFRESH_DURATION: Duration = Duration::from_millis(2000);
+pub const REASSIGN_COOLDOWN: Duration = Duration::from_millis(5000);
 
 // TaskState::Success means all subnodes and this node have received and succeeded task tid,
 //     this indicates that the subswarm has succeeded task tid.
@@ -34,6 +40,8 @@ pub enum TaskState {
 
 // this is a state machine of the node.
 // but need to ensure the coherence of the whole swarm.
+// transitions: Free -> InTask(id, InProgress) -> InTask(id, Success/Failure) -> Free/InTask(id_new, InProgress),
+//              InTask(id, InProgress) -> InTask(id_new, InProgress) (preemption by a task of higher priority).
 enum NodeState {
     Free,
     InTask(u32, TaskState),
@@ -43,6 +51,7 @@ struct Node {
     desc: NodeDesc,
     details: NodeDetails,
     last_heard: Instant,
+    pending: bool,  // adopted as a child by rebalancing, but not switched to this node yet
 }
 
 impl Node {
@@ -50,6 +59,69 @@ impl Node {
     pub fn get_id(&self) -> u32 { self.desc.get_id() }
 }
 
+// a child being moved to another node of the same tree, started by this node.
+// phase 1: this node sends AssignChild to the new parent,
+//          which takes the child as pending and answers ChildAssigned, or answers ChildRefused.
+// phase 2: this node sends ChangeParent to the child, which switches to the new parent and Leaves this node.
+// the move is over once ChangeParent is sent, or the move is refused, or the child is gone.
+// if any message is lost, the child stays with this node, and the new parent drops it on timeout.
+struct Rebalancing {
+    cid: u32,
+    pid_new: u32,
+    start_t: Instant,
+}
+
+// the replica last sent by the root node to its successor.
+// the replica is sent again only when it changes, or the successor changes, or it has not been sent for a while,
+// as it carries all tasks of the swarm.
+struct Succession {
+    sid: u32,
+    stamp: Vec<u32>,
+    t: Instant,
+}
+
+// a partial task delegated by the root node to the subswarm of one of its children.
+// the child divides the task as the top node of its subswarm, like root node does for a task of the whole swarm.
+struct Delegation {
+    task: Task,  // stamped with the revision `div`
+    cid: u32,
+    start_t: Instant,
+}
+
+// policy of choosing a parent among the nodes of another swarm.
+// a lower score means a better parent:
+// closer nodes are preferred, as well as nodes with fewer children and at shallower depth,
+// so that task relaying latency stays low and subtask division stays balanced.
+struct ParentScoring {
+    max_children: u32,
+    max_depth: u32,
+    distance_scale: f32,
+    children_weight: f32,
+    depth_weight: f32,
+}
+
+impl ParentScoring {
+    pub fn new(conf: &AstroConf, distance_scale: f32) -> ParentScoring {
+        ParentScoring {
+            max_children: conf.max_children,
+            max_depth: conf.max_depth,
+            distance_scale,
+            children_weight: conf.parent_children_weight,
+            depth_weight: conf.parent_depth_weight,
+        }
+    }
+
+    pub fn is_within_limits(&self, desc: &NodeDesc) -> bool {
+        desc.chd < self.max_children && desc.get_depth() < self.max_depth
+    }
+
+    pub fn score(&self, desc: &NodeDesc, p_self: &PosVec) -> f32 {
+        distance(&desc.p, p_self) / self.distance_scale
+            + self.children_weight * (desc.chd as f32)
+            + self.depth_weight * (desc.get_depth() as f32)
+    }
+}
+
 pub struct NodeManager {
     conf: Rc<AstroConf>,
     now: Instant,
@@ -59,10 +131,22 @@ pub struct NodeManager {
 
     nid: Nid,
     state: NodeState,
+    failure_reason: Option<FailureReason>,  // why the current task has failed in this subswarm, if known
     tm: TaskManager,
-
-    parent: Option<Node>,  // need backup ids (indirect upper nodes / sibling nodes)
+    task_start_t: Instant,  // when the root node started the current task
+    station_held: bool,  // whether the root node has reported the success of the current task and keeps station
+    formation_degraded: bool,  // whether the formation error of the swarm keeping station is above the threshold
+    reassign_t: Instant,  // when the degraded formation was last divided again
+    census: Option<CensusCollector>,
+    last_census_qid: Option<u32>,
+
+    parent: Option<Node>,
+    backup_parents: Vec<u32>,  // ranked ids of nodes to re-attach to when parent gets lost
+    predecessor: Option<u32>,  // id of the lost root node, if this node has taken over as root
+    succession: Option<Succession>,
     children: Vec<Node>,
+    rebalancing: Option<Rebalancing>,
+    delegations: Vec<Delegation>,  // partial tasks run concurrently by children of the root node
     node_lost_duration: Duration,
     connection_range: f32,
     state_msg_duration: Duration,
@@ -81,10 +165,22 @@ impl NodeManager {
 
             nid: root_nid(conf.id),
             state: NodeState::Free,
+            failure_reason: None,
             tm: TaskManager::new(),
+            task_start_t: now,
+            station_held: false,
+            formation_degraded: false,
+            reassign_t: now,
+            census: None,
+            last_census_qid: None,
 
             parent: None,
+            backup_parents: vec![],
+            predecessor: None,
+            succession: None,
             children: vec![],
+            rebalancing: None,
+            delegations: vec![],
             node_lost_duration: DEFAULT_NODE_LOST_DURATION,
             connection_range: f32::min(conf.contact_range * DEFAULT_CONNECTION_RANGE_TO_CONTACT_RANGE_RATIO,
                 conf.uav_radius * DEFAULT_CONNECTION_RANGE_TO_RADIUS_RATIO),
@@ -133,9 +229,21 @@ impl NodeManager {
         self.children.iter().any(|cnd| cnd.get_id() == id_other)
     }
 
+    #[inline]
+    pub fn get_depth(&self) -> u32 { self.nid.len() as u32 }
+
+    // whether one more child can be added without exceeding the fan-out and depth limits
+    pub fn has_room_for_child(&self) -> bool {
+        (self.children.len() as u32) < self.conf.max_children && self.get_depth() < self.conf.max_depth
+    }
+
     #[inline]
     pub fn is_free(&self) -> bool { !self.has_task() }
 
+    // free, and no child runs a delegated task
+    #[inline]
+    pub fn is_idle(&self) -> bool { self.is_free() && self.delegations.is_empty() }
+
     pub fn has_task(&self) -> bool {
         match self.state {
             NodeState::InTask(..) => true,
@@ -157,8 +265,19 @@ impl NodeManager {
         }
     }
 
+    // children switched to this node, i.e. all children except those adopted by rebalancing and yet to switch.
+    // a pending child is counted in the subswarm of its old parent until it switches.
+    fn get_joined_children(&self) -> impl Iterator<Item = &Node> {
+        self.children.iter().filter(|cnd| !cnd.pending)
+    }
+
+    // children taking part in the task of this node, i.e. all children except those running delegated tasks
+    fn get_task_children(&self) -> impl Iterator<Item = &Node> {
+        self.get_joined_children().filter(|cnd| !self.delegations.iter().any(|dlg| dlg.cid == cnd.get_id()))
+    }
+
     pub fn all_child_subswarms_alignment_done_for_task(&self, tid: u32) -> bool {
-        self.children.iter().all(|cnd| cnd.details.is_subswm_alignment_done_for_tsk(tid))
+        self.get_task_children().all(|cnd| cnd.details.is_subswm_alignment_done_for_tsk(tid))
     }
 
     pub fn get_subswarm_task_state(&self) -> SubswarmTaskState {
@@ -180,7 +299,7 @@ impl NodeManager {
     }
 
     pub fn get_subswarm_size(&self) -> u32 {
-        1 + self.children.iter().map(|cnd| cnd.details.subswarm).sum::<u32>()
+        1 + self.get_joined_children().map(|cnd| cnd.details.subswarm).sum::<u32>()
     }
 
     pub fn get_swarm_size(&self) -> u32 {
@@ -190,6 +309,17 @@ impl NodeManager {
         }
     }
 
+    // root node designates the child with the biggest subswarm as its successor,
+    // so that the most nodes stay on the tree if root node gets lost.
+    pub fn get_successor_id(&self) -> Option<u32> {
+        match &self.parent {
+            None => self.get_joined_children().max_by(|cnd1, cnd2| {
+                cnd1.details.subswarm.cmp(&cnd2.details.subswarm).then(cnd2.get_id().cmp(&cnd1.get_id()))
+            }).map(|cnd| cnd.get_id()),
+            Some(pnd) => pnd.desc.suc,
+        }
+    }
+
     pub fn generate_node_desc(&self) -> NodeDesc {
         NodeDesc {
             nid: self.nid.clone(),
@@ -197,14 +327,60 @@ impl NodeManager {
             v: self.v,
             swm: self.get_swarm_size(),
             tsk: self.get_task_id(),
+            chd: self.children.len() as u32,
+            suc: self.get_successor_id(),
+            clk: self.get_choreography_clock(),
+            asg: self.get_child_task_assignments(),
+            tgt: self.tm.get_current_task().and_then(|td| td.get_target(self.now)),
         }
     }
 
+    fn get_choreography_clock(&self) -> Option<Duration> {
+        self.tm.get_current_task()?.get_own_subtask()?.get_clock(self.now)
+    }
+
+    // None if all children follow the task of this node
+    fn get_child_task_assignments(&self) -> Option<Vec<(u32, u32)>> {
+        if self.delegations.is_empty() {
+            return None;
+        }
+        let mut asg: Vec<(u32, u32)> = self.delegations.iter().map(|dlg| (dlg.cid, dlg.task.id)).collect();
+        if let Some(tid) = self.get_task_id() {
+            asg.extend(self.get_task_children().map(|cnd| (cnd.get_id(), tid)));
+        }
+        Some(asg)
+    }
+
     pub fn generate_node_details(&self) -> NodeDetails {
         NodeDetails {
             subswarm: self.get_subswarm_size(),
             subswm_tsk: self.get_subswarm_task_state(),
+            div: self.get_parent_id().map_or(0, |pid| self.tm.get_current_subtask_div_from(pid)),
+            rsn: self.failure_reason,
+            ctr: Some(self.get_subswarm_centroid()),
+            cov: self.tm.get_current_task().map_or(vec![], |td| td.get_covered()),
+            err: self.get_subswarm_pos_error(),
+        }
+    }
+
+    // None if this node has no divided task
+    fn get_subswarm_pos_error(&self) -> Option<PosError> {
+        let tid = self.get_task_id()?;
+        let te = self.tm.get_current_task()?.get_own_subtask()?;
+        let mut err = PosError::of_uav(te.calc_pos_error(&self.p, self.now));
+        for cnd in self.get_task_children().filter(|cnd| cnd.details.is_subswm_alignment_done_for_tsk(tid)) {
+            if let Some(child_err) = &cnd.details.err {
+                err.merge(child_err);
+            }
         }
+        Some(err)
+    }
+
+    pub fn get_subswarm_centroid(&self) -> PosVec {
+        let sum = self.get_joined_children().fold(self.p, |sum, cnd| {
+            sum + cnd.details.ctr.unwrap_or(cnd.desc.p) * cnd.details.subswarm as f32
+        });
+        sum / self.get_subswarm_size() as f32
     }
 
     pub fn update_node(&mut self, p: &PosVec, v: &Velocity,
@@ -217,29 +393,33 @@ impl NodeManager {
         self.v = *v;
 
         // messages may be lost, so in most cases they should carry state, rather than carry events.
-        // if an event message is not received by all parties involved, its (partial) effect should be revocable.
-        // currently this is not achieved.
+        // event messages between uavs are acknowledged and retransmitted by the delivery layer under `Control`,
+        // which gives up on receivers that stay silent. so an event may still not reach all parties involved,
+        // and its (partial) effect should be revocable. currently this is not achieved.
         let mut msgs_out: Vec<Msg> = vec![];
 
-        self.remove_no_contact_nodes(rm);  // contact-losing events
+        msgs_out.append(&mut self.remove_no_contact_nodes(rm, neighbours));  // contact-losing events
         for msg in msgs {  // message events
             msgs_out.append(&mut self.process_msg(msg, neighbours));
         }
-        self.remove_no_connection_nodes();  // connection-losing events
+        msgs_out.append(&mut self.remove_no_connection_nodes(neighbours));  // connection-losing events
 
-        self.manage_node_state();
+        msgs_out.append(&mut self.manage_node_state());
+        msgs_out.append(&mut self.maybe_finish_census());
         self.maybe_generate_node_state_msg(neighbours, &mut msgs_out);
 
         (self.calc_next_v(), msgs_out)
     }
 
-    fn remove_no_contact_nodes(&mut self, rm: &Vec<u32>) {
+    fn remove_no_contact_nodes(&mut self, rm: &Vec<u32>, neighbours: &Vec<&Contact>) -> Vec<Msg> {
+        let mut msgs: Vec<Msg> = vec![];
         if self.parent.as_ref().is_some_and(|pnd| rm.contains(&pnd.get_id())) {
-            self.remove_parent();
+            msgs = self.fail_over_parent(neighbours);
         }
         for cid in rm {
             self.remove_child_of_id(*cid);
         }
+        msgs
     }
 
     fn process_msg(&mut self, msg: &Msg, neighbours: &Vec<&Contact>) -> Vec<Msg> {
@@ -253,20 +433,32 @@ impl NodeManager {
             MsgBody::Accept => (),
             MsgBody::Reject => self.remove_parent_of_id(desc_sdr.get_id()),
             MsgBody::Leave => self.remove_child_of_id(desc_sdr.get_id()),
+            MsgBody::Reattach(appl) => msg_out.push(self.add_reattached_child_or_reject(desc_sdr, appl)),
 
-            MsgBody::ChangeParent(pid_new) => self.change_parent(*pid_new, neighbours),
-            MsgBody::AssignChild(appl) => self.add_assigned_child(appl, neighbours),
+            MsgBody::ChangeParent(pid_new) => msg_out.append(&mut self.change_parent(desc_sdr, *pid_new, neighbours)),
+            MsgBody::AssignChild(appl) => msg_out.append(&mut self.add_assigned_child(desc_sdr, appl, neighbours)),
+            MsgBody::ChildAssigned(cid) => msg_out.append(&mut self.move_assigned_child(desc_sdr, *cid)),
+            MsgBody::ChildRefused(cid) => self.end_rebalancing(desc_sdr.get_id(), *cid),
 
             MsgBody::Task(task) => msg_out.append(&mut self.relay_or_accept_task(task)),
-            MsgBody::Subtask(subtask) => self.allocate_subtask(subtask),
+            MsgBody::CancelTask(tid) => msg_out.append(&mut self.relay_or_cancel_task(*tid)),
+            MsgBody::Target(target) => msg_out.append(&mut self.relay_or_follow_target(desc_sdr, target)),
+            MsgBody::TaskCancelled(_) | MsgBody::TaskReport(_) | MsgBody::FormationReport(_) => (),
+            MsgBody::CensusQuery(query) => msg_out.append(&mut self.relay_or_start_census(desc_sdr, query)),
+            MsgBody::CensusReply(reply) => self.add_census_reply(desc_sdr, reply),
+            MsgBody::Succession(replica) => self.store_task_replica(desc_sdr, replica),
+            MsgBody::Subtask(subtask) => self.allocate_subtask(desc_sdr, subtask),
+
+            MsgBody::Reliable(..) | MsgBody::Ack(_) => (),  // unwrapped and consumed by the delivery layer
         }
         msg_out
     }
 
-    fn remove_no_connection_nodes(&mut self) {
+    fn remove_no_connection_nodes(&mut self, neighbours: &Vec<&Contact>) -> Vec<Msg> {
+        let mut msgs: Vec<Msg> = vec![];
         if let Some(pnd) = &self.parent {
             if self.now - pnd.last_heard > self.node_lost_duration {
-                self.remove_parent();
+                msgs = self.fail_over_parent(neighbours);
             }
         }
         let rm: Vec<u32> = self.children.iter().filter(
@@ -275,15 +467,17 @@ impl NodeManager {
         for cid in rm {
             self.remove_child_of_id(cid);
         }
+        msgs
     }
 
-    fn manage_node_state(&mut self) {
+    fn manage_node_state(&mut self) -> Vec<Msg> {
         // child nodes generally follow the state of their parent node,
         // but they themselves decide whether to go into success/failure.
         match self.state {
             NodeState::InTask(tid, TaskState::InProgress | TaskState::Success) => {
-                if self.children.iter().any(|cnd| cnd.details.is_subswm_failure_in_tsk(tid)) {
-                    self.fail_task();
+                let failed_child = self.get_task_children().find(|cnd| cnd.details.is_subswm_failure_in_tsk(tid));
+                if let Some(reason) = failed_child.map(|cnd| cnd.details.rsn) {
+                    self.fail_task(reason);
                 } else if self.all_child_subswarms_alignment_done_for_task(tid) {
                     // only after subswarm aligned (which means accurate subswarm size), can the top node divide task.
                     self.advance_task(tid);
@@ -293,7 +487,9 @@ impl NodeManager {
         }
 
         if self.is_root_node() {
-            self.manage_root_node_state();  // manages the overall state of the whole swarm
+            self.manage_root_node_state()  // manages the overall state of the whole swarm
+        } else {
+            vec![]
         }
     }
 
@@ -301,16 +497,18 @@ impl NodeManager {
         if let NodeState::InTask(tid, TaskState::InProgress) = self.state {
             match self.tm.get_current_task() {
                 Some(td) if td.is_task_divided() => {  // "task divided" indicates subswarm aligned
-                    self.children.iter().filter(|cnd|
+                    // a child also needs its subtask again after re-division
+                    self.get_task_children().filter(|cnd|
                         cnd.details.is_subswm_alignment_done_for_tsk(tid)  // this alignment condition is redundant
-                        && !cnd.details.is_subswm_allocation_done_for_tsk(tid)
-                    ).map(|cnd| {
+                        && (!cnd.details.is_subswm_allocation_done_for_tsk(tid) || cnd.details.div != td.get_div())
+                    ).filter_map(|cnd| {
+                        // a child re-attached after task division has no subtask from this node
                         let cid = cnd.get_id();
-                        Msg {
+                        td.get_child_subtask(cid).map(|subtask| Msg {
                             sender: self.generate_node_desc(),
                             to_ids: vec![cid],
-                            body: MsgBody::Subtask(td.get_child_subtask(cid).unwrap().clone()),
-                        }
+                            body: MsgBody::Subtask(subtask.clone()),
+                        })
                     }).collect::<Vec<Msg>>()
                 },
                 _ => vec![],
@@ -323,55 +521,265 @@ impl NodeManager {
     // this function should be called only after subswarm aligned.
     // for root node of swarm, "task allocated (by gcs)" does not ensures swarm aligned.
     // for other nodes, "subtask allocated (by parent)" happens after subswarm aligned.
+    //
+    // the task is re-divided whenever children or their subswarm sizes change,
+    // so that losing a uav degrades the formation rather than failing the task.
+    // only when the subswarm gets too small for the task, does the top node fail the task.
+    // other nodes wait for their parents to re-divide.
+    // a task that cannot be divided for other reasons fails at any node.
     fn advance_task(&mut self, tid: u32) {
-        let children = &self.children;
-        match self.tm.get_current_task_mut() {
-            None => (),  // subtask not allocated by parent yet
-            Some(td) => {  // subtask allocated, for non-root node, also means subswarm aligned
-                assert!(tid == td.get_tid());
-                if !td.is_task_divided() {
-                    let children_info: Vec<ChildInfo> = children.iter().map(|cnd| ChildInfo {
-                        id: cnd.get_id(),
-                        subswm_size: cnd.details.subswarm,
-                    }).collect();
-                    td.divide_task(&children_info, self.conf.contact_range);
+        match self.tm.get_current_task() {
+            None => { return; },  // subtask not allocated by parent yet
+            Some(td) => { assert!(tid == td.get_tid()); },  // subtask allocated, for non-root node, also means subswarm aligned
+        };
+        let children_info: Vec<ChildInfo> = self.get_task_children().map(|cnd| ChildInfo {
+            id: cnd.get_id(),
+            subswm_size: cnd.details.subswarm,
+            ctr: cnd.details.ctr.unwrap_or(cnd.desc.p),
+        }).collect();
+        // the top node of a task is the node whose parent runs another task or none
+        let is_top_node = !self.parent.as_ref().is_some_and(|pnd| pnd.desc.has_task_of_id(tid));
+        match self.tm.ensure_task_divided(&children_info, self.conf.contact_range, &self.p, self.now) {
+            Err(TaskError::TooFewUavs) if !is_top_node => { return; },
+            Err(err) => {
+                println!("uav {} fails to divide task {}: {:?}", self.get_id(), tid, err);
+                self.fail_task(Some(FailureReason::Division(err)));
+                return;
+            },
+            Ok(true) => {  // newly divided, success needs to be achieved again
+                self.switch_state_to_in_task(tid, TaskState::InProgress);
+            },
+            Ok(false) => (),
+        }
+        // lanes covered by children are kept, so that they are not re-assigned after a child gets lost
+        let child_cov: Vec<u32> = self.get_task_children()
+            .filter(|cnd| cnd.details.is_subswm_alignment_done_for_tsk(tid))
+            .flat_map(|cnd| cnd.details.cov.iter().copied()).collect();
+        let td = self.tm.get_current_task_mut().unwrap();
+        let div = td.get_div();
+        let te = td.get_own_subtask_mut().unwrap();
+        let result = te.advance(&self.p, self.now);
+        let (has_motion, is_playing, failure) = (te.has_motion(), te.is_playing(), te.get_failure());
+        let own_cov = te.get_covered().to_vec();
+        if td.mark_covered(own_cov.iter().chain(&child_cov)) && is_top_node {
+            println!("task {} covered {:.0}%", tid, td.calc_coverage().unwrap_or(0.0) * 100.0);
+        }
+        match result {
+            Some(false) => {  // execution failure
+                println!("uav {} fails task {}: {:?}", self.get_id(), tid, failure);
+                self.fail_task(failure);
+            },
+            Some(true) => {  // execution success
+                if self.get_task_children().all(|cnd| cnd.details.is_subswm_success_in_tsk(tid) && cnd.details.div == div) {
+                    if has_motion && !is_playing && is_top_node {  // initial shape formed by the subswarm
+                        let te = self.tm.get_current_task_mut().unwrap().get_own_subtask_mut().unwrap();
+                        te.set_clock_start(self.now);
+                        println!("choreography of task {} started by {}", tid, self.get_id());
+                    } else {
+                        self.switch_state_to_in_task(tid, TaskState::Success);
+                        self.tm.get_current_task_mut().unwrap().clear_task_deadline();
+                    }
                 }
-                let te = td.get_own_subtask_mut().unwrap();
-                match te.advance(&self.p, self.now) {
-                    Some(false) => self.fail_task(),  // execution failure
-                    Some(true) => {  // execution success
-                        if self.children.iter().all(|cnd| cnd.details.is_subswm_success_in_tsk(tid)) {
-                            self.switch_state_to_in_task(tid, TaskState::Success);
-                        }
-                    },
-                    None => (),  // execution in progress, if already success, may need to switch node state back
+            },
+            None => {  // execution in progress
+                if is_playing && matches!(self.state, NodeState::InTask(_, TaskState::Success)) {
+                    // the initial shape succeeded, the choreography has not
+                    self.switch_state_to_in_task(tid, TaskState::InProgress);
                 }
             },
-        };
+        }
     }
 
     // this function is only run by root node
-    fn manage_root_node_state(&mut self) {
-        if let NodeState::InTask(tid, ts) = self.state {
-            match ts {
-                TaskState::InProgress => { return; },  // do nothing
-                TaskState::Success => { println!("task {} succeeded", tid); },
-                TaskState::Failure => { println!("task {} failed", tid); },
+    fn manage_root_node_state(&mut self) -> Vec<Msg> {
+        let constraints = self.get_task_constraints();
+        for tid in self.tm.requeue_deferred_tasks(self.get_swarm_size(), |t| t.validate(&constraints).is_ok()) {
+            println!("task {} queued, the swarm has grown", tid);
+        }
+        let mut msgs: Vec<Msg> = self.manage_delegations();
+        if let NodeState::InTask(tid, TaskState::Success) = self.state {
+            if self.is_keeping_station() {
+                msgs.append(&mut self.keep_station(tid));
+                if !self.tm.has_queued_task() || !matches!(self.state, NodeState::InTask(_, TaskState::Success)) {
+                    return msgs;  // keeping on, or re-assigning, or failed
+                }
+                println!("task {} stops keeping station", tid);
+                return self.start_next_task(msgs);  // success has been reported already
             }
-            // task failure, or task success
         }
-        // free, or task failure, or task success
+        if let NodeState::InTask(tid, ts) = self.state {
+            let outcome = match ts {
+                TaskState::InProgress if self.tm.has_preempting_task() => {
+                    self.tm.preempt_current_task();
+                    println!("task {} preempted", tid);
+                    TaskOutcome::Preempted
+                },
+                TaskState::InProgress => { return msgs; },  // do nothing
+                TaskState::Success => { println!("task {} succeeded", tid); TaskOutcome::Success },
+                TaskState::Failure => { println!("task {} failed", tid); TaskOutcome::Failure },
+            };
+            let reason = if outcome == TaskOutcome::Failure { self.failure_reason } else { None };
+            msgs.push(self.generate_task_report_msg(tid, outcome, self.get_subswarm_size(), self.task_start_t, reason));
+            // task failure, or task success, or task preempted
+        }
+        // free, or task failure, or task success, or task preempted
+        self.start_next_task(msgs)
+    }
+
+    // this function is only run by root node
+    fn start_next_task(&mut self, msgs: Vec<Msg>) -> Vec<Msg> {
+        self.station_held = false;
+        self.formation_degraded = false;
         match self.tm.pop_queued_task() {
             Some(t) => {  // swarm from free/task to another task
                 let tid = t.id;
                 self.switch_state_to_in_task(tid, TaskState::InProgress);
-                self.tm.set_current_task(t);
+                self.tm.set_current_task(t, self.now);
+                self.task_start_t = self.now;
                 println!("task {} received by {}", tid, self.get_id());
             },
             None => {  // swarm from task/free to free
                 self.switch_state_to_free();
             },
         }
+        msgs
+    }
+
+    fn is_keeping_station(&self) -> bool {
+        self.tm.get_current_task().is_some_and(|td| td.get_task().station.is_some())
+    }
+
+    // this function is only run by root node, after the current task has succeeded.
+    // the success is reported once, then the formation error of the swarm is watched,
+    // and reported to gcs whenever it goes above the threshold or back below it.
+    // a degraded formation to be re-assigned is divided again after each cooldown, until it is restored.
+    fn keep_station(&mut self, tid: u32) -> Vec<Msg> {
+        let mut msgs: Vec<Msg> = vec![];
+        if !self.station_held {
+            self.station_held = true;
+            println!("task {} succeeded, keeping station", tid);
+            msgs.push(self.generate_task_report_msg(tid, TaskOutcome::Success, self.get_subswarm_size(),
+                self.task_start_t, None));
+        }
+        let stn = self.tm.get_current_task().unwrap().get_task().station.clone().unwrap();
+        let err = self.get_subswarm_pos_error().unwrap_or_default();
+        let degraded = err.calc_rms() > stn.threshold;
+        if degraded == self.formation_degraded {
+            if degraded && stn.policy == StationPolicy::Reassign && self.now - self.reassign_t >= REASSIGN_COOLDOWN {
+                println!("formation of task {} still degraded, divided again", tid);
+                self.reassign_formation();
+            }
+            return msgs;
+        }
+        self.formation_degraded = degraded;
+        println!("formation of task {} {}: rms {:.2}m, max {:.2}m", tid,
+            if degraded { "degraded" } else { "restored" }, err.calc_rms(), err.max);
+        msgs.push(Msg {
+            sender: self.generate_node_desc(),
+            to_ids: vec![GCS_ID],
+            body: MsgBody::FormationReport(FormationReport { tid, degraded, rms: err.calc_rms(), max: err.max }),
+        });
+        if degraded {
+            match stn.policy {
+                StationPolicy::Reconverge => (),  // the uavs are flying back anyway
+                StationPolicy::Reassign => self.reassign_formation(),
+                StationPolicy::Fail => { self.fail_task(Some(FailureReason::FormationDegraded)); },
+            }
+        }
+        msgs
+    }
+
+    fn reassign_formation(&mut self) {
+        self.tm.get_current_task_mut().unwrap().outdate_division();
+        self.reassign_t = self.now;
+    }
+
+    // this function is only run by root node.
+    // delegated tasks end when the subswarm of the child succeeds or fails, or when the child gets lost.
+    // partial tasks in the queue are delegated to free children, each to the smallest subswarm big enough.
+    // a task of the whole swarm takes all children not running delegated tasks,
+    // so partial tasks queued after it wait until it ends.
+    fn manage_delegations(&mut self) -> Vec<Msg> {
+        let mut msgs: Vec<Msg> = vec![];
+        let mut ended: Vec<(usize, TaskOutcome, u32, Option<FailureReason>)> = vec![];
+        for (idx, dlg) in self.delegations.iter().enumerate() {
+            let tid = dlg.task.id;
+            match self.children.iter().find(|cnd| cnd.get_id() == dlg.cid) {
+                None => ended.push((idx, TaskOutcome::Failure, 0, None)),
+                Some(cnd) if cnd.details.is_subswm_failure_in_tsk(tid) => {
+                    ended.push((idx, TaskOutcome::Failure, cnd.details.subswarm, cnd.details.rsn));
+                },
+                Some(cnd) if cnd.details.is_subswm_success_in_tsk(tid) && cnd.details.div == dlg.task.div => {
+                    ended.push((idx, TaskOutcome::Success, cnd.details.subswarm, None));
+                },
+                Some(_) => (),
+            }
+        }
+        for (idx, outcome, uavs, reason) in ended.into_iter().rev() {
+            let dlg = self.delegations.remove(idx);
+            println!("delegated task {} {:?} by {}", dlg.task.id, outcome, dlg.cid);
+            msgs.push(self.generate_task_report_msg(dlg.task.id, outcome, uavs, dlg.start_t, reason));
+        }
+
+        let free_children: Vec<(u32, u32)> = self.get_task_children().filter(
+            |cnd| cnd.desc.is_free()
+        ).map(|cnd| (cnd.get_id(), cnd.details.subswarm)).collect();
+        let mut taken: Vec<u32> = vec![];
+        while let Some(task) = self.tm.dispatch_partial_task(
+            |t| Self::find_delegate(t, &free_children, &taken).is_some()
+        ) {
+            let cid = Self::find_delegate(&task, &free_children, &taken).unwrap();
+            taken.push(cid);
+            let div = self.tm.new_div();
+            println!("task {} delegated to {}", task.id, cid);
+            self.delegations.push(Delegation {
+                task: Task {
+                    comm_point: None,
+                    div,
+                    ..task
+                },
+                cid,
+                start_t: self.now,
+            });
+        }
+        msgs
+    }
+
+    // out: id of the free child with the smallest subswarm that is big enough for the task
+    fn find_delegate(task: &Task, free_children: &[(u32, u32)], taken: &[u32]) -> Option<u32> {
+        let least_uavs = u32::max(task.uavs, task.num_least_uavs());
+        free_children.iter().filter(|(cid, subswm_size)| {
+            !taken.contains(cid) && *subswm_size >= least_uavs && task.subtree.is_none_or(|id| id == *cid)
+        }).min_by_key(|(cid, subswm_size)| (*subswm_size, *cid)).map(|(cid, _)| *cid)
+    }
+
+    // the child is sent the delegated task once its subswarm has aligned to it, and again if not allocated
+    fn generate_delegation_msgs(&self) -> Vec<Msg> {
+        self.delegations.iter().filter(|dlg| self.children.iter().any(|cnd| {
+            let tid = dlg.task.id;
+            cnd.get_id() == dlg.cid && cnd.details.is_subswm_alignment_done_for_tsk(tid)
+                && (!cnd.details.is_subswm_allocation_done_for_tsk(tid) || cnd.details.div != dlg.task.div)
+        })).map(|dlg| Msg {
+            sender: self.generate_node_desc(),
+            to_ids: vec![dlg.cid],
+            body: MsgBody::Subtask(dlg.task.clone()),
+        }).collect()
+    }
+
+    fn generate_task_report_msg(&self, tid: u32, outcome: TaskOutcome, uavs: u32, start_t: Instant,
+                                reason: Option<FailureReason>) -> Msg {
+        Msg {
+            sender: self.generate_node_desc(),
+            to_ids: vec![GCS_ID],
+            body: MsgBody::TaskReport(TaskReport {
+                tid,
+                outcome,
+                uavs,
+                elapsed: self.now - start_t,
+                reason,
+                coverage: self.tm.get_current_task().filter(|td| td.get_tid() == tid).and_then(|td| td.calc_coverage()),
+            }),
+        }
     }
 
     fn maybe_generate_node_state_msg(&mut self, neighbours: &Vec<&Contact>, msgs_out: &mut Vec<Msg>) {
@@ -379,14 +787,20 @@ impl NodeManager {
         // however currently node status changes cannot be detected.
         // as a makeshift, check whether `msgs_out` isn't empty.
         if self.now - self.last_state_msg_t > self.state_msg_duration {  // limit frequency
-            if self.is_free() {
+            if self.is_idle() {
                 msgs_out.append(&mut self.try_join_other_swarm(neighbours));
+                msgs_out.append(&mut self.try_rebalance_children());
             } else {
                 msgs_out.append(&mut self.generate_task_related_msgs());
             }
+            msgs_out.append(&mut self.generate_delegation_msgs());
             if self.has_connections() {
                 msgs_out.push(self.generate_connection_msg());
             }
+            if let Some(msg) = self.generate_succession_msg() {
+                msgs_out.push(msg);
+            }
+            self.refresh_backup_parents(neighbours);
             self.last_state_msg_t = self.now;
         }
     }
@@ -421,22 +835,20 @@ impl NodeManager {
     fn find_parent_candidate<'a, 'b, 'c>(&self, desc_self: &'a NodeDesc, neighbours: &Vec<&'b Contact>)
     -> Option<&'c NodeDesc> where 'a: 'c, 'b: 'c {
         let root_id_self = self.get_root_id();
+        let scoring = ParentScoring::new(&self.conf, self.connection_range);
         let mut candidates: Vec<&NodeDesc> = neighbours.iter().filter(
             |t| self.now - t.last_heard < NEW_PARENT_FRESHNESS  // freshness of candidate
         ).map(|t| &t.desc).filter(
             |nd| nd.is_free() && nd.get_root_id() != root_id_self  // no task, in different swarm
-        ).collect();
-        candidates.push(&desc_self);
+        ).filter(|nd| scoring.is_within_limits(nd)).collect();
+        candidates.push(desc_self);
         candidates.sort_unstable_by(|desc1, desc2| {
             let cmp_swm = desc2.swm.cmp(&desc1.swm);  // bigger swarm size
             if cmp_swm != Ordering::Equal { return cmp_swm; }
             let cmp_root_id = desc1.get_root_id().cmp(&desc2.get_root_id());  // smaller root id
             if cmp_root_id != Ordering::Equal { return cmp_root_id; }
-            let cmp_dist = distance(&desc1.p, &self.p).partial_cmp(
-                &distance(&desc2.p, &self.p)).unwrap();  // closer node
-            cmp_dist
-            // may also take into account the number of children, but NodeDesc doesn't carry this info
-            // may also take into account the depth(rank) of the node on the swarm tree
+            // within the same swarm, lower score
+            scoring.score(desc1, &self.p).partial_cmp(&scoring.score(desc2, &self.p)).unwrap()
         });
         let candidate: &NodeDesc = candidates.first().unwrap();
         if candidate.get_id() == desc_self.get_id() {  // all other swarms are worse than the current swarm
@@ -446,6 +858,229 @@ impl NodeManager {
         }
     }
 
+    // rebalancing runs only while the swarm is free.
+    // it moves at most one child at a time, to bound fan-out and depth,
+    // and to keep each child geometrically close to its parent.
+    fn try_rebalance_children(&mut self) -> Vec<Msg> {
+        if let Some(rb) = &self.rebalancing {
+            if self.now - rb.start_t < REBALANCING_TIMEOUT {
+                return vec![];  // previous move not finished yet
+            }
+            self.rebalancing = None;
+        }
+        match self.find_rebalancing_move() {
+            Some((cid, pid_new)) => {
+                let cnd = self.children.iter().find(|cnd| cnd.get_id() == cid).unwrap();
+                let msg = Msg {
+                    sender: self.generate_node_desc(),
+                    to_ids: vec![pid_new],
+                    body: MsgBody::AssignChild(AssignChildAppl {
+                        cid,
+                        dtl: cnd.details.clone(),
+                    }),
+                };
+                self.rebalancing = Some(Rebalancing {
+                    cid,
+                    pid_new,
+                    start_t: self.now,
+                });
+                vec![msg]
+            },
+            None => vec![],
+        }
+    }
+
+    // out: (id of the child to move, id of its new parent)
+    fn find_rebalancing_move(&self) -> Option<(u32, u32)> {
+        if self.child_adding_rate >= CHILD_ADDING_RATE_LIMIT {
+            return None;  // tree still growing
+        }
+        let stable_children: Vec<&Node> = self.get_joined_children().filter(
+            |cnd| self.now - cnd.last_heard < NEW_PARENT_FRESHNESS && cnd.desc.is_free()
+        ).collect();
+        let depth = self.get_depth();
+        let max_depth = self.conf.max_depth;
+
+        // children too deep, move one of them up to the parent of this node
+        if depth + 1 > max_depth {
+            if let Some(pnd) = self.parent.as_ref().filter(|pnd| self.now - pnd.last_heard < NEW_PARENT_FRESHNESS) {
+                return stable_children.iter().min_by(|cnd1, cnd2| {
+                    distance(&cnd1.desc.p, &pnd.desc.p).partial_cmp(&distance(&cnd2.desc.p, &pnd.desc.p)).unwrap()
+                }).map(|cnd| (cnd.get_id(), pnd.get_id()));
+            }
+            return None;
+        }
+
+        // children can only be moved down to a sibling if the depth allows
+        if depth + 2 > max_depth {
+            return None;
+        }
+        let too_many_children = self.children.len() as u32 > self.conf.max_children;
+        let mut best_move: Option<(u32, u32, f32)> = None;
+        for cnd in &stable_children {
+            let dist_to_self = distance(&cnd.desc.p, &self.p);
+            if !too_many_children && dist_to_self <= self.connection_range {
+                continue;  // child already close to this node
+            }
+            for snd in &stable_children {
+                if snd.get_id() == cnd.get_id() || snd.desc.chd >= self.conf.max_children {
+                    continue;
+                }
+                let dist = distance(&cnd.desc.p, &snd.desc.p);
+                if dist > self.connection_range || (!too_many_children && dist >= dist_to_self) {
+                    continue;  // sibling too far away to be a better parent
+                }
+                if best_move.is_none_or(|(_, _, d)| dist < d) {
+                    best_move = Some((cnd.get_id(), snd.get_id(), dist));
+                }
+            }
+        }
+        best_move.map(|(cid, pid_new, _)| (cid, pid_new))
+    }
+
+    // rank of a node as a backup parent, lower is better, `None` if not eligible.
+    // rank 0: grandparent, or designated successor if parent is root node.
+    // rank 1: other nodes on the same tree, outside the subtree of the current parent.
+    // eligible nodes are on the same tree and share the current task, and are not descendants of this node.
+    // siblings and their descendants may lose the parent at the same time, so they are never eligible,
+    // otherwise two of them could pick each other.
+    fn rank_backup_parent(&self, desc: &NodeDesc) -> Option<u32> {
+        if !self.has_parent() {
+            return None;
+        }
+        if self.parent.as_ref().is_some_and(|pnd| pnd.desc.is_root_node() && pnd.desc.suc == Some(desc.get_id())) {
+            // the successor may have already taken over as root
+            return if desc.get_id() != self.get_id() && desc.tsk == self.get_task_id() { Some(0) } else { None };
+        }
+        let len = self.nid.len();
+        if desc.get_root_id() != self.get_root_id() || desc.tsk != self.get_task_id()
+            || !self.is_valid_descendant_of(desc) || desc.nid.starts_with(&self.nid[..len - 1]) {
+            return None;
+        }
+        if len >= 3 && desc.get_id() == self.nid[len - 3] {
+            Some(0)
+        } else {
+            Some(1)
+        }
+    }
+
+    fn refresh_backup_parents(&mut self, neighbours: &Vec<&Contact>) {
+        let mut backups: Vec<(u32, f32, u32)> = neighbours.iter().filter_map(|t| {
+            self.rank_backup_parent(&t.desc).map(|rank| (rank, distance(&t.desc.p, &self.p), t.desc.get_id()))
+        }).collect();
+        backups.sort_unstable_by(|(rank1, dist1, _), (rank2, dist2, _)| {
+            rank1.cmp(rank2).then(dist1.partial_cmp(dist2).unwrap())
+        });
+        self.backup_parents = backups.into_iter().map(|(_, _, id)| id).collect();
+    }
+
+    // on parent loss, re-attach to the best backup parent still in contact,
+    // keeping the node id on the same tree and the task state.
+    // if no backup parent is available, this node becomes a free root node.
+    fn fail_over_parent(&mut self, neighbours: &Vec<&Contact>) -> Vec<Msg> {
+        if self.is_successor_of_parent() {
+            self.take_over_as_root();
+            return vec![];
+        }
+        let src_tree = self.get_root_id();
+        let backup: Option<NodeDesc> = self.backup_parents.iter().find_map(|bid| {
+            neighbours.iter().find(|t| t.desc.get_id() == *bid
+                && self.now - t.last_heard < NEW_PARENT_FRESHNESS
+                && self.rank_backup_parent(&t.desc).is_some()
+            ).map(|t| t.desc.clone())
+        });
+        self.backup_parents.clear();
+        match backup {
+            Some(desc) if self.set_parent(&desc) => {
+                vec![Msg {
+                    sender: self.generate_node_desc(),
+                    to_ids: vec![desc.get_id()],
+                    body: MsgBody::Reattach(JoinAppl {
+                        dtl: self.generate_node_details(),
+                        src_tree,
+                    }),
+                }]
+            },
+            _ => {
+                self.remove_parent();
+                vec![]
+            },
+        }
+    }
+
+    fn is_successor_of_parent(&self) -> bool {
+        self.parent.as_ref().is_some_and(|pnd| pnd.desc.is_root_node() && pnd.desc.suc == Some(self.get_id()))
+    }
+
+    // the lost root node's tasks carry on with this node as the new root node.
+    // the current task keeps running if this node has been aligned to it,
+    // otherwise it is restarted from the front of the task queue.
+    fn take_over_as_root(&mut self) {
+        let pid = self.parent.as_ref().unwrap().get_id();
+        self.parent = None;
+        self.nid = root_nid(self.get_id());
+        self.predecessor = Some(pid);
+        match self.tm.take_replica(pid) {
+            Some(TaskReplica { task, queue, deferred, old }) => {
+                self.tm.add_old_tasks(&old);
+                for t in &queue {
+                    self.tm.add_task_if_new(t);
+                }
+                for t in &deferred {
+                    self.tm.defer_task(t, 0);
+                }
+                match task {
+                    Some(t) if self.has_task_of_id(t.id) => {
+                        // the current task is only the subtask from the lost parent,
+                        // the whole task is divided again over the swarm reattaching to this node
+                        let tid = t.id;
+                        self.tm.set_current_task(t, self.now);
+                        self.switch_state_to_in_task(tid, TaskState::InProgress);
+                    },
+                    Some(t) => {
+                        self.switch_state_to_free();
+                        self.tm.push_front_queued_task(t);
+                    },
+                    None => self.switch_state_to_free(),
+                }
+            },
+            None => self.switch_state_to_free(),
+        }
+        println!("root failover: {} takes over from {}", self.get_id(), pid);
+    }
+
+    fn generate_succession_msg(&mut self) -> Option<Msg> {
+        if !self.is_root_node() {
+            self.succession = None;
+            return None;
+        }
+        let sid = self.get_successor_id()?;
+        // delegated tasks are restarted by the successor
+        let mut replica = self.tm.generate_replica();
+        replica.queue.extend(self.delegations.iter().map(|dlg| Task {
+            div: 0,
+            ..dlg.task.clone()
+        }));
+        let stamp = replica.get_stamp();
+        if self.succession.as_ref().is_some_and(|scs| {
+            scs.sid == sid && scs.stamp == stamp && self.now - scs.t < SUCCESSION_REFRESH_DURATION
+        }) {
+            return None;
+        }
+        self.succession = Some(Succession { sid, stamp, t: self.now });
+        Some(Msg {
+            sender: self.generate_node_desc(),
+            to_ids: vec![sid],
+            body: MsgBody::Succession(replica),
+        })
+    }
+
+    fn store_task_replica(&mut self, desc_sdr: &NodeDesc, replica: &TaskReplica) {
+        if desc_sdr.is_root_node() && self.has_parent_of_id(desc_sdr.get_id()) {
+            self.tm.store_replica(desc_sdr.get_id(), replica);
+        }
+    }
+
     fn generate_connection_msg(&self) -> Msg {
         let mut to_ids: Vec<u32> = self.children.iter().map(|cnd| cnd.get_id()).collect();
         if let Some(pnd) = &self.parent {
@@ -498,7 +1133,7 @@ impl NodeManager {
                 if let Some(te) = td.get_own_subtask() {
                     // task velocity generated only after task divided,
                     // maybe for root node this can be before task division.
-                    return Some(te.calc_task_velocity(&self.p, self.conf.max_v));
+                    return Some(te.calc_task_velocity(&self.p, self.conf.max_v, self.now));
                 }
             }
         }
@@ -533,6 +1168,7 @@ impl NodeManager {
                 cnd.desc = desc.clone();
                 cnd.details = dtl.clone();
                 cnd.last_heard = self.now;
+                cnd.pending = false;
             }  // otherwise, just wait the child to timeout and be removed
         }
     }
@@ -542,7 +1178,7 @@ impl NodeManager {
         let id_other = desc.get_id();
         let accept: bool = self.get_root_id() != appl.src_tree
             && self.is_free() && self.child_adding_rate < CHILD_ADDING_RATE_LIMIT
-            && self.add_child(desc, &appl.dtl);
+            && self.has_room_for_child() && self.add_child(desc, &appl.dtl);
         Msg {
             sender: self.generate_node_desc(),
             to_ids: vec![id_other],
@@ -550,17 +1186,45 @@ impl NodeManager {
         }
     }
 
-    fn change_parent(&mut self, pid_new: u32, neighbours: &Vec<&Contact>) {
-        let mut success: bool = false;
-        if let Some(t) = neighbours.iter().find(|t| t.desc.get_id() == pid_new) {
-            success = self.set_parent(&t.desc);
+    // deal with Reattach, a node on the same tree lost its parent.
+    // fan-out limit is not applied, rebalancing will fix it after the swarm is free.
+    fn add_reattached_child_or_reject(&mut self, desc: &NodeDesc, appl: &JoinAppl) -> Msg {
+        let id_other = desc.get_id();
+        let accept: bool = (self.get_root_id() == appl.src_tree || self.predecessor == Some(appl.src_tree))
+            && desc.tsk == self.get_task_id()
+            && self.add_child(desc, &appl.dtl);
+        Msg {
+            sender: self.generate_node_desc(),
+            to_ids: vec![id_other],
+            body: if accept { MsgBody::Accept } else { MsgBody::Reject },
         }
-        if !success {
-            self.remove_parent();  // node detached from current swarm tree
+    }
+
+    // only the current parent may move this node to a new parent.
+    // if the new parent is not reachable, this node stays with the current parent,
+    // and tells the new parent to drop this node.
+    fn change_parent(&mut self, desc_sdr: &NodeDesc, pid_new: u32, neighbours: &Vec<&Contact>) -> Vec<Msg> {
+        let pid = desc_sdr.get_id();
+        if !self.has_parent_of_id(pid) {
+            return vec![];
         }
+        let root_id = self.get_root_id();
+        let success: bool = match neighbours.iter().find(|t| t.desc.get_id() == pid_new) {
+            Some(t) => t.desc.get_root_id() == root_id && self.set_parent(&t.desc),
+            None => false,
+        };
+        vec![Msg {
+            sender: self.generate_node_desc(),
+            to_ids: vec![if success { pid } else { pid_new }],
+            body: MsgBody::Leave,
+        }]
     }
 
-    fn add_assigned_child(&mut self, appl: &AssignChildAppl, neighbours: &Vec<&Contact>) {
+    fn add_assigned_child(&mut self, desc_sdr: &NodeDesc, appl: &AssignChildAppl, neighbours: &Vec<&Contact>)
+    -> Vec<Msg> {
+        if self.is_free() {
+            return self.adopt_rebalanced_child(desc_sdr, appl, neighbours);
+        }
         let success: bool =
         if let Some(t) = neighbours.iter().find(|t| t.desc.get_id() == appl.cid) {
             self.get_root_id() == t.desc.get_root_id()
@@ -570,7 +1234,55 @@ impl NodeManager {
             false
         };
         if !success {
-            self.fail_task();
+            self.fail_task(None);
+        }
+        vec![]
+    }
+
+    // phase 1 of rebalancing, on the new parent side.
+    // the child is taken as pending, it will time out if it never switches to this node.
+    fn adopt_rebalanced_child(&mut self, desc_sdr: &NodeDesc, appl: &AssignChildAppl, neighbours: &Vec<&Contact>)
+    -> Vec<Msg> {
+        let root_id = self.get_root_id();
+        let cid = appl.cid;
+        let is_new = !self.has_child_of_id(cid);
+        let success: bool = self.has_room_for_child() && desc_sdr.get_root_id() == root_id
+            && match neighbours.iter().find(|t| t.desc.get_id() == cid) {
+                Some(t) => t.desc.get_root_id() == root_id && t.desc.is_free()
+                    && t.desc.has_parent_of_id(desc_sdr.get_id())
+                    && self.add_child(&t.desc, &appl.dtl),
+                None => false,
+            };
+        if success && is_new {
+            self.children.iter_mut().find(|cnd| cnd.get_id() == cid).unwrap().pending = true;
+        }
+        vec![Msg {
+            sender: self.generate_node_desc(),
+            to_ids: vec![desc_sdr.get_id()],
+            body: if success { MsgBody::ChildAssigned(cid) } else { MsgBody::ChildRefused(cid) },
+        }]
+    }
+
+    // phase 2 of rebalancing, on the old parent side.
+    fn move_assigned_child(&mut self, desc_sdr: &NodeDesc, cid: u32) -> Vec<Msg> {
+        let pid_new = desc_sdr.get_id();
+        let confirmed = self.rebalancing.as_ref().is_some_and(|rb| rb.cid == cid && rb.pid_new == pid_new)
+            && self.is_free() && self.has_child_of_id(cid);
+        if !confirmed {
+            return vec![];
+        }
+        self.end_rebalancing(pid_new, cid);
+        vec![Msg {
+            sender: self.generate_node_desc(),
+            to_ids: vec![cid],
+            body: MsgBody::ChangeParent(pid_new),
+        }]
+    }
+
+    // the next move may start at once
+    fn end_rebalancing(&mut self, pid_new: u32, cid: u32) {
+        if self.rebalancing.as_ref().is_some_and(|rb| rb.cid == cid && rb.pid_new == pid_new) {
+            self.rebalancing = None;
         }
     }
 
@@ -581,16 +1293,196 @@ impl NodeManager {
                 to_ids: vec![pid],
                 body: MsgBody::Task(task.clone()),
             }],
-            None => {  // root node
-                self.tm.add_task_if_new(task);
+            None => {  // root node, deadlines not given by gcs are set by configuration
+                let mut task = task.clone();
+                task.reach_timeout.get_or_insert(self.conf.reach_timeout);
+                if !task.is_open_ended() {
+                    task.task_timeout.get_or_insert(self.conf.task_timeout);
+                }
+                if let Some(tf) = task.transform.as_mut().filter(|tf| tf.relative) {
+                    tf.anchor += self.p;
+                    tf.relative = false;
+                }
+                if !self.tm.is_task_new(&task) {
+                    return vec![];
+                }
+                let constraints = self.get_task_constraints();
+                if let Err(err) = task.validate(&constraints) {
+                    // a task only too big for the current swarm waits for the swarm to grow, gcs is not told
+                    if err == TaskError::TooFewUavs
+                        && task.validate(&TaskConstraints { swarm_size: None, ..constraints }).is_ok() {
+                        println!("task {} deferred until the swarm grows", task.id);
+                        self.tm.defer_task(&task, self.get_swarm_size());
+                        return vec![];
+                    }
+                    println!("task {} rejected: {:?}", task.id, err);
+                    self.tm.reject_task(task.id);
+                    return vec![self.generate_task_report_msg(task.id, TaskOutcome::Rejected, 0, self.now,
+                        Some(FailureReason::Invalid(err)))];
+                }
+                self.tm.add_task_if_new(&task);
                 vec![]
             },
         }
     }
 
-    fn allocate_subtask(&mut self, subtask: &Task) {
-        if self.has_task_of_id(subtask.id) && self.tm.get_current_task().is_none() {
-            self.tm.set_current_task(subtask.clone());
+    fn get_task_constraints(&self) -> TaskConstraints {
+        TaskConstraints {
+            min_spacing: 2.0 * self.conf.uav_radius,
+            swarm_size: Some(self.get_swarm_size()),
+        }
+    }
+
+    // the cancelled task is aborted if it is current, the swarm then follows the root node back to free,
+    // or to the next queued task.
+    fn relay_or_cancel_task(&mut self, tid: u32) -> Vec<Msg> {
+        match self.get_parent_id() {
+            Some(pid) => vec![Msg {  // relay cancellation to parent node
+                sender: self.generate_node_desc(),
+                to_ids: vec![pid],
+                body: MsgBody::CancelTask(tid),
+            }],
+            None => {  // root node
+                let mut msgs: Vec<Msg> = vec![];
+                if self.has_task_of_id(tid) {
+                    msgs.push(self.generate_task_report_msg(tid, TaskOutcome::Cancelled,
+                        self.get_subswarm_size(), self.task_start_t, None));
+                    self.switch_state_to_free();
+                }
+                if let Some(idx) = self.delegations.iter().position(|dlg| dlg.task.id == tid) {
+                    let dlg = self.delegations.remove(idx);
+                    msgs.push(self.generate_task_report_msg(tid, TaskOutcome::Cancelled, 0, dlg.start_t, None));
+                }
+                self.tm.cancel_task(tid);
+                println!("task {} cancelled", tid);
+                msgs.push(Msg {
+                    sender: self.generate_node_desc(),
+                    to_ids: vec![GCS_ID],
+                    body: MsgBody::TaskCancelled(tid),
+                });
+                msgs
+            },
+        }
+    }
+
+    // a target update from gcs or from a child goes up to the root node, which follows it,
+    // or passes it down to the child running the task as a delegated task.
+    // other nodes follow the target through the node description of their parents.
+    fn relay_or_follow_target(&mut self, desc_sdr: &NodeDesc, target: &Target) -> Vec<Msg> {
+        let from_parent = self.has_parent_of_id(desc_sdr.get_id());
+        match self.get_parent_id() {
+            Some(pid) if !from_parent => vec![Msg {  // relay target update to parent node
+                sender: self.generate_node_desc(),
+                to_ids: vec![pid],
+                body: MsgBody::Target(*target),
+            }],
+            _ => {  // root node, or the top node of a delegated task
+                if let Some(td) = self.tm.get_current_task_mut() {
+                    td.follow_target(target, self.now);
+                }
+                self.delegations.iter().filter(|dlg| dlg.task.id == target.tid).map(|dlg| Msg {
+                    sender: self.generate_node_desc(),
+                    to_ids: vec![dlg.cid],
+                    body: MsgBody::Target(*target),
+                }).collect()
+            },
+        }
+    }
+
+    // a query from the parent goes down, a query from gcs or from a child goes up.
+    fn relay_or_start_census(&mut self, desc_sdr: &NodeDesc, query: &CensusQuery) -> Vec<Msg> {
+        let sid = desc_sdr.get_id();
+        if self.has_parent_of_id(sid) {
+            return self.start_census(query, sid);
+        }
+        match self.get_parent_id() {
+            Some(pid) => vec![Msg {  // relay query to parent node
+                sender: self.generate_node_desc(),
+                to_ids: vec![pid],
+                body: MsgBody::CensusQuery(query.clone()),
+            }],
+            None => {  // root node, the same query may arrive via different nodes
+                if self.last_census_qid == Some(query.qid) {
+                    vec![]
+                } else {
+                    self.last_census_qid = Some(query.qid);
+                    self.start_census(query, GCS_ID)
+                }
+            },
+        }
+    }
+
+    fn start_census(&mut self, query: &CensusQuery, reply_to: u32) -> Vec<Msg> {
+        let entry = CensusEntry {
+            nid: self.nid.clone(),
+            p: self.p,
+            v: self.v,
+            state: self.get_subswarm_task_state(),
+        };
+        let cids: Vec<u32> = self.get_joined_children().map(|cnd| cnd.get_id()).collect();
+        let collector = CensusCollector::new(query, reply_to, CensusData::from_entry(query.mode, entry),
+            cids.clone(), self.now);
+        let msgs = if cids.is_empty() {
+            vec![]
+        } else {
+            vec![Msg {
+                sender: self.generate_node_desc(),
+                to_ids: cids,
+                body: MsgBody::CensusQuery(collector.get_child_query()),
+            }]
+        };
+        self.census = Some(collector);  // an unfinished previous census is dropped
+        msgs
+    }
+
+    fn add_census_reply(&mut self, desc_sdr: &NodeDesc, reply: &CensusReply) {
+        if let Some(collector) = self.census.as_mut().filter(|c| c.get_qid() == reply.qid) {
+            collector.add_reply(desc_sdr.get_id(), reply.data.clone());
+        }
+    }
+
+    fn maybe_finish_census(&mut self) -> Vec<Msg> {
+        if !self.census.as_ref().is_some_and(|c| c.is_done(self.now)) {
+            return vec![];
+        }
+        let collector = self.census.take().unwrap();
+        let (qid, reply_to, page_size) = (collector.get_qid(), collector.get_reply_to(), collector.get_page_size());
+        if reply_to == GCS_ID {
+            let pages = collector.into_data().into_pages(page_size);
+            let num_pages = pages.len() as u32;
+            pages.into_iter().enumerate().map(|(idx, data)| Msg {
+                sender: self.generate_node_desc(),
+                to_ids: vec![GCS_ID],
+                body: MsgBody::CensusReply(CensusReply {
+                    qid,
+                    data,
+                    page: idx as u32,
+                    pages: num_pages,
+                }),
+            }).collect()
+        } else if self.has_parent_of_id(reply_to) {
+            vec![Msg {
+                sender: self.generate_node_desc(),
+                to_ids: vec![reply_to],
+                body: MsgBody::CensusReply(CensusReply {
+                    qid,
+                    data: collector.into_data(),
+                    page: 0,
+                    pages: 1,
+                }),
+            }]
+        } else {
+            vec![]  // parent changed, the census reply is of no use
+        }
+    }
+
+    // a subtask from the latest division of the parent replaces the current one.
+    fn allocate_subtask(&mut self, desc_sdr: &NodeDesc, subtask: &Task) {
+        let pid = desc_sdr.get_id();
+        if self.has_task_of_id(subtask.id) && self.has_parent_of_id(pid)
+            && !self.tm.is_current_subtask_from(pid, subtask.div) {
+            self.tm.set_current_subtask(subtask.clone(), pid, self.now);
+            self.switch_state_to_in_task(subtask.id, TaskState::InProgress);
         }
     }
 
@@ -603,8 +1495,14 @@ impl NodeManager {
                 details: NodeDetails {
                     subswarm: 0,  // value here should not matter
                     subswm_tsk: SubswarmTaskState::None,  // value here should not matter
+                    div: 0,  // value here should not matter
+                    rsn: None,  // value here should not matter
+                    ctr: None,  // value here should not matter
+                    cov: vec![],  // value here should not matter
+                    err: None,  // value here should not matter
                 },
                 last_heard: self.now,
+                pending: false,
             });
             self.on_parent_info_updated();
             true
@@ -628,15 +1526,32 @@ impl NodeManager {
         let pnd = self.parent.as_ref().unwrap();
         self.nid = pnd.desc.nid.clone();
         self.nid.push(id);
-        match pnd.desc.tsk {
+        let (clk, ptsk, tgt) = (pnd.desc.clk, pnd.desc.tsk, pnd.desc.tgt);
+        match pnd.desc.get_child_task_id(id) {
             None => { self.switch_state_to_free(); },
             Some(tid) => {
                 if !self.has_task_of_id(tid) {
-                    // non-root node may re-enter InProgress state from previous InProgress state
+                    // non-root node may re-enter InProgress state from previous InProgress state,
+                    // e.g. when the task of its parent is preempted by a task of higher priority,
+                    // in which case it re-aligns to the new task id.
                     self.switch_state_to_in_task(tid, TaskState::InProgress);
                 }
             },
         };
+        if let (Some(clk), Some(tid)) = (clk, ptsk) {  // parent playing a choreography
+            if self.has_task_of_id(tid) {
+                if let Some(te) = self.tm.get_current_task_mut().and_then(|td| td.get_own_subtask_mut()) {
+                    te.sync_clock(clk, self.now);
+                }
+            }
+        }
+        if let Some(tgt) = tgt {  // parent tracking a target
+            if self.has_task_of_id(tgt.tid) {
+                if let Some(td) = self.tm.get_current_task_mut() {
+                    td.follow_target(&tgt, self.now);
+                }
+            }
+        }
     }
 
     fn add_child(&mut self, desc: &NodeDesc, dtl: &NodeDetails) -> bool {
@@ -649,6 +1564,7 @@ impl NodeManager {
                     desc: desc.clone(),
                     details: dtl.clone(),
                     last_heard: self.now,
+                    pending: false,
                 });
                 self.child_adding_rate += 1.0;
                 println!("new connection: {:?} <- {}", self.get_nid(), id_other);
@@ -657,32 +1573,31 @@ impl NodeManager {
         }
     }
 
-    // do not use this function when rearrange child to another node within same tree.
-    // as when rearranging child, the removal of a child shall not cause task to fail.
+    // losing a child does not fail the task, the task gets re-divided among the remaining children.
     fn remove_child_of_id(&mut self, cid: u32) {
         if let Some(idx) = self.children.iter().position(|cnd| cnd.get_id() == cid) {
             let cnd = self.children.remove(idx);
+            if self.rebalancing.as_ref().is_some_and(|rb| rb.cid == cid) {
+                self.rebalancing = None;
+            }
             self.child_adding_rate -= 1.0;
             println!("delete connection: {:?} <-x {}", self.get_nid(), cnd.get_id());
-            if let Some(tid) = self.get_task_id() {
-                if cnd.desc.has_task_of_id(tid) {  // a child which has received task tid got lost
-                    self.fail_task();
-                }
-            }
         }
     }
 
-    fn fail_task(&mut self) {
+    fn fail_task(&mut self, reason: Option<FailureReason>) {
         match self.state {
             NodeState::Free => (),
             NodeState::InTask(tid, _) => {
                 self.switch_state_to_in_task(tid, TaskState::Failure);
+                self.failure_reason = reason;
             },
         };
     }
 
     fn switch_state_to_free(&mut self) {
         self.state = NodeState::Free;
+        self.failure_reason = None;
         self.tm.clear_current_task();
     }
 
@@ -693,8 +1608,473 @@ impl NodeManager {
             false
         };
         self.state = NodeState::InTask(tid, ts);
+        if !keep_task || !matches!(ts, TaskState::Failure) {
+            self.failure_reason = None;
+        }
         if !keep_task {
             self.tm.clear_current_task();
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::msg::{CensusMode, Line, StationKeeping};
+
+    fn conf_of(id: u32) -> Rc<AstroConf> {
+        Rc::new(AstroConf {
+            id,
+            uav_radius: 0.5,
+            msg_range: 100.0,
+            contact_range: 50.0,
+            max_v: 5.0,
+            max_children: 4,
+            max_depth: 8,
+            parent_children_weight: 0.1,
+            parent_depth_weight: 0.1,
+            reach_timeout: Duration::from_secs(30),
+            task_timeout: Duration::from_secs(60),
+        })
+    }
+
+    fn node_of(id: u32) -> NodeManager {
+        NodeManager::new_root_node(&conf_of(id), &PosVec::zero(), &Velocity::zero())
+    }
+
+    fn desc_of(nid: &[u32]) -> NodeDesc {
+        NodeDesc { nid: nid.to_vec(), ..NodeDesc::get_gcs_desc() }
+    }
+
+    fn details_of(subswarm: u32) -> NodeDetails {
+        NodeDetails {
+            subswarm,
+            subswm_tsk: SubswarmTaskState::None,
+            div: 0,
+            rsn: None,
+            ctr: None,
+            cov: vec![],
+            err: None,
+        }
+    }
+
+    // a child heard from just now, with a subswarm of the given size
+    fn add_child_of(nm: &mut NodeManager, cid: u32, subswarm: u32) {
+        let mut nid = nm.nid.clone();
+        nid.push(cid);
+        nm.children.push(Node { desc: desc_of(&nid), details: details_of(subswarm), last_heard: nm.now, pending: false });
+    }
+
+    fn place_child(nm: &mut NodeManager, cid: u32, x: f32) {
+        nm.children.iter_mut().find(|cnd| cnd.get_id() == cid).unwrap().desc.p = PosVec { x, y: 0.0, z: 0.0 };
+    }
+
+    fn contact_of(desc: NodeDesc, now: Instant) -> Contact {
+        Contact { desc, last_heard: now }
+    }
+
+    // root node 1 with children 2 and 3, where 2 is far from 1 and close to 3
+    fn unbalanced_root() -> NodeManager {
+        let mut nm = node_of(1);
+        add_child_of(&mut nm, 2, 1);
+        add_child_of(&mut nm, 3, 1);
+        place_child(&mut nm, 2, 30.0);
+        place_child(&mut nm, 3, 25.0);
+        nm
+    }
+
+    // a line needing an uav at each end
+    fn line_task_of(id: u32) -> Task {
+        Task {
+            id,
+            lines: vec![Line {
+                points: vec![PosVec { x: 0.0, y: 0.0, z: 10.0 }, PosVec { x: 10.0, y: 0.0, z: 10.0 }],
+                curves: vec![],
+                start: true,
+                end: true,
+            }],
+            duration: Duration::from_secs(1),
+            ..Default::default()
+        }
+    }
+
+    fn reports_of(msgs: &[Msg]) -> Vec<(u32, TaskOutcome)> {
+        msgs.iter().filter_map(|msg| match &msg.body {
+            MsgBody::TaskReport(report) => Some((report.tid, report.outcome)),
+            _ => None,
+        }).collect()
+    }
+
+    fn replica_of(task: Option<u32>, queue: &[u32], old: &[u32]) -> TaskReplica {
+        TaskReplica {
+            task: task.map(line_task_of),
+            queue: queue.iter().map(|tid| line_task_of(*tid)).collect(),
+            deferred: vec![],
+            old: old.to_vec(),
+        }
+    }
+
+    // a child of root node 1, designated as its successor
+    fn successor_of_root(id: u32, tsk: Option<u32>, replica: &TaskReplica) -> NodeManager {
+        let mut nm = node_of(id);
+        let root_desc = NodeDesc { tsk, suc: Some(id), swm: 2, ..desc_of(&[1]) };
+        assert!(nm.set_parent(&root_desc));
+        nm.store_task_replica(&root_desc, replica);
+        nm
+    }
+
+    #[test]
+    fn succession_is_sent_on_change_and_refreshed() {
+        let mut nm = node_of(1);
+        add_child_of(&mut nm, 2, 1);
+        assert!(nm.generate_succession_msg().is_some_and(|msg| msg.to_ids == vec![2]));
+        assert!(nm.generate_succession_msg().is_none());
+        nm.relay_or_accept_task(&line_task_of(5));
+        assert!(nm.generate_succession_msg().is_some());
+        nm.manage_root_node_state();  // task 5 started
+        assert!(nm.generate_succession_msg().is_some());
+        assert!(nm.generate_succession_msg().is_none());
+        nm.now += SUCCESSION_REFRESH_DURATION;
+        assert!(nm.generate_succession_msg().is_some());
+        // a bigger subswarm makes another successor
+        add_child_of(&mut nm, 3, 2);
+        assert!(nm.generate_succession_msg().is_some_and(|msg| msg.to_ids == vec![3]));
+    }
+
+    #[test]
+    fn successor_takes_over_the_tasks_of_lost_root() {
+        let mut nm = successor_of_root(2, Some(5), &replica_of(Some(5), &[6], &[4]));
+        assert!(nm.has_task_of_id(5));
+        assert!(nm.fail_over_parent(&vec![]).is_empty());
+        assert!(nm.is_root_node());
+        assert_eq!(nm.get_nid(), &vec![2]);
+        assert_eq!(nm.predecessor, Some(1));
+        // the whole task is divided again by this node
+        assert!(nm.has_task_of_id(5));
+        assert!(nm.tm.get_current_task().is_some_and(|td| td.get_task().div == 0));
+        assert!(nm.tm.has_queued_task());
+        // copies of an ended task still on their way are not accepted again
+        assert!(nm.relay_or_accept_task(&line_task_of(4)).is_empty());
+        assert!(!nm.tm.is_task_new(&line_task_of(4)));
+        assert!(!nm.tm.is_task_new(&line_task_of(6)));
+    }
+
+    #[test]
+    fn successor_not_aligned_restarts_the_current_task() {
+        let mut nm = successor_of_root(2, None, &replica_of(Some(5), &[6], &[]));
+        assert!(nm.is_free());
+        nm.fail_over_parent(&vec![]);
+        assert!(nm.is_free());
+        add_child_of(&mut nm, 3, 1);
+        nm.manage_root_node_state();
+        assert!(nm.has_task_of_id(5));
+    }
+
+    #[test]
+    fn child_is_moved_in_two_phases() {
+        let mut old = unbalanced_root();
+        let msgs = old.try_rebalance_children();
+        assert_eq!(msgs.len(), 1);
+        assert!(msgs[0].to_ids == vec![3] && matches!(&msgs[0].body, MsgBody::AssignChild(appl) if appl.cid == 2));
+        assert!(old.try_rebalance_children().is_empty());  // one move at a time
+
+        // phase 1, the new parent takes the child as pending
+        let mut new = node_of(3);
+        new.p = PosVec { x: 25.0, y: 0.0, z: 0.0 };
+        assert!(new.set_parent(&old.generate_node_desc()));
+        let moved = contact_of(NodeDesc { p: PosVec { x: 30.0, y: 0.0, z: 0.0 }, ..desc_of(&[1, 2]) }, new.now);
+        let msgs = new.process_msg(&msgs[0], &vec![&moved]);
+        assert!(msgs[0].to_ids == vec![1] && matches!(msgs[0].body, MsgBody::ChildAssigned(2)));
+        assert!(new.has_child_of_id(2));
+        assert_eq!(new.get_subswarm_size(), 1);  // still counted by the old parent
+
+        // phase 2, the old parent moves the child, and is free to start another move
+        let msgs = old.process_msg(&msgs[0], &vec![]);
+        assert!(msgs[0].to_ids == vec![2] && matches!(msgs[0].body, MsgBody::ChangeParent(3)));
+        assert!(old.rebalancing.is_none());
+
+        let mut child = node_of(2);
+        assert!(child.set_parent(&old.generate_node_desc()));
+        let msgs = child.process_msg(&msgs[0], &vec![&contact_of(new.generate_node_desc(), child.now)]);
+        assert!(msgs[0].to_ids == vec![1] && matches!(msgs[0].body, MsgBody::Leave));
+        assert_eq!(child.get_nid(), &vec![1, 3, 2]);
+        old.process_msg(&msgs[0], &vec![]);
+        assert!(!old.has_child_of_id(2));
+        new.update_connection(&child.generate_node_desc(), &child.generate_node_details());
+        assert_eq!(new.get_subswarm_size(), 2);
+    }
+
+    #[test]
+    fn refused_move_ends_at_once() {
+        let mut old = unbalanced_root();
+        let msgs = old.try_rebalance_children();
+        let mut new = node_of(3);
+        assert!(new.set_parent(&old.generate_node_desc()));
+        let msgs = new.process_msg(&msgs[0], &vec![]);  // the child is out of contact of the new parent
+        assert!(msgs[0].to_ids == vec![1] && matches!(msgs[0].body, MsgBody::ChildRefused(2)));
+        assert!(!new.has_child_of_id(2));
+        old.process_msg(&msgs[0], &vec![]);
+        assert!(old.rebalancing.is_none());
+        assert_eq!(old.try_rebalance_children().len(), 1);
+        // a lost child ends the move as well
+        old.remove_child_of_id(2);
+        assert!(old.rebalancing.is_none());
+    }
+
+    #[test]
+    fn pending_child_is_left_out_of_subswarm() {
+        let mut nm = node_of(1);
+        add_child_of(&mut nm, 2, 1);
+        add_child_of(&mut nm, 3, 5);
+        nm.children[1].pending = true;
+        assert_eq!(nm.get_subswarm_size(), 2);
+        assert_eq!(nm.get_successor_id(), Some(2));
+        assert_eq!(nm.start_census(&CensusQuery { qid: 1, mode: CensusMode::Summary, page_size: 10,
+            budget: Duration::from_secs(1) }, GCS_ID)[0].to_ids, vec![2]);
+    }
+
+    // node 3 under node 2, under root node 1
+    fn grandchild() -> NodeManager {
+        let mut nm = node_of(3);
+        assert!(nm.set_parent(&desc_of(&[1, 2])));
+        nm
+    }
+
+    #[test]
+    fn backup_parents_are_outside_the_subtree_of_parent() {
+        let nm = grandchild();
+        let rank = |nid: &[u32]| nm.rank_backup_parent(&desc_of(nid));
+        assert_eq!(rank(&[1]), Some(0));  // grandparent
+        assert_eq!(rank(&[1, 6]), Some(1));  // uncle
+        assert_eq!(rank(&[1, 6, 7]), Some(1));  // cousin
+        assert_eq!(rank(&[1, 2]), None);  // parent
+        assert_eq!(rank(&[1, 2, 4]), None);  // sibling
+        assert_eq!(rank(&[1, 2, 4, 5]), None);  // nephew
+        assert_eq!(rank(&[1, 2, 3, 8]), None);  // own child
+        assert_eq!(rank(&[9]), None);  // another tree
+        assert_eq!(nm.rank_backup_parent(&NodeDesc { tsk: Some(5), ..desc_of(&[1, 6]) }), None);  // another task
+        // the successor of the root node, though a sibling, takes over as root
+        let mut nm = node_of(3);
+        assert!(nm.set_parent(&NodeDesc { suc: Some(4), ..desc_of(&[1]) }));
+        assert_eq!(nm.rank_backup_parent(&desc_of(&[1, 4])), Some(0));
+        assert_eq!(nm.rank_backup_parent(&desc_of(&[1, 5])), None);
+    }
+
+    #[test]
+    fn parent_failover_reattaches_to_best_backup() {
+        let mut nm = grandchild();
+        let now = nm.now;
+        let (uncle, sibling) = (contact_of(desc_of(&[1, 6]), now), contact_of(desc_of(&[1, 2, 4]), now));
+        let grandparent = contact_of(desc_of(&[1]), now);
+        nm.refresh_backup_parents(&vec![&sibling, &uncle, &grandparent]);
+        assert_eq!(nm.backup_parents, vec![1, 6]);
+        // the grandparent has been lost as well
+        let msgs = nm.fail_over_parent(&vec![&sibling, &uncle]);
+        assert!(msgs[0].to_ids == vec![6] && matches!(msgs[0].body, MsgBody::Reattach(_)));
+        assert_eq!(nm.get_nid(), &vec![1, 6, 3]);
+    }
+
+    #[test]
+    fn siblings_losing_parent_do_not_pick_each_other() {
+        let mut nm = grandchild();
+        let sibling = contact_of(desc_of(&[1, 2, 4]), nm.now);
+        nm.refresh_backup_parents(&vec![&sibling]);
+        assert!(nm.fail_over_parent(&vec![&sibling]).is_empty());
+        assert!(nm.is_root_node());
+        assert_eq!(nm.get_nid(), &vec![3]);
+    }
+
+    fn is_cancel_ack_of(msg: &Msg, tid: u32) -> bool {
+        msg.to_ids == vec![GCS_ID] && matches!(msg.body, MsgBody::TaskCancelled(id) if id == tid)
+    }
+
+    // root node 1 of two uavs running task 5, with task 6 queued
+    fn root_in_task() -> NodeManager {
+        let mut nm = node_of(1);
+        add_child_of(&mut nm, 2, 1);
+        nm.relay_or_accept_task(&line_task_of(5));
+        nm.relay_or_accept_task(&line_task_of(6));
+        nm.manage_root_node_state();
+        assert!(nm.has_task_of_id(5));
+        nm
+    }
+
+    #[test]
+    fn cancelling_current_task_reports_and_moves_on() {
+        let mut nm = root_in_task();
+        let msgs = nm.relay_or_cancel_task(5);
+        assert_eq!(reports_of(&msgs), vec![(5, TaskOutcome::Cancelled)]);
+        assert!(is_cancel_ack_of(msgs.last().unwrap(), 5));
+        assert!(nm.is_free());
+        nm.manage_root_node_state();
+        assert!(nm.has_task_of_id(6));
+        assert!(nm.relay_or_accept_task(&line_task_of(5)).is_empty());
+        assert!(!nm.tm.has_queued_task());
+    }
+
+    #[test]
+    fn cancelling_queued_task_leaves_current_task_running() {
+        let mut nm = root_in_task();
+        let msgs = nm.relay_or_cancel_task(6);
+        assert!(reports_of(&msgs).is_empty());
+        assert_eq!(msgs.len(), 1);
+        assert!(is_cancel_ack_of(&msgs[0], 6));
+        assert!(nm.has_task_of_id(5));
+        assert!(!nm.tm.has_queued_task());
+    }
+
+    #[test]
+    fn task_of_higher_priority_preempts_current_task() {
+        let mut nm = root_in_task();
+        nm.relay_or_accept_task(&Task { priority: 1, ..line_task_of(7) });
+        let msgs = nm.manage_root_node_state();
+        assert_eq!(reports_of(&msgs), vec![(5, TaskOutcome::Preempted)]);
+        assert!(nm.has_task_of_id(7));
+    }
+
+    #[test]
+    fn partial_task_goes_to_smallest_fitting_child() {
+        let free_children = [(2, 3), (3, 5), (4, 2)];
+        let task = Task { uavs: 3, ..line_task_of(8) };
+        assert_eq!(NodeManager::find_delegate(&task, &free_children, &[]), Some(2));
+        assert_eq!(NodeManager::find_delegate(&task, &free_children, &[2]), Some(3));
+        assert_eq!(NodeManager::find_delegate(&task, &free_children, &[2, 3]), None);
+        // the least uavs of the shape count as well
+        let task = Task { uavs: 1, ..line_task_of(8) };
+        assert_eq!(NodeManager::find_delegate(&task, &free_children, &[]), Some(4));
+        let task = Task { subtree: Some(3), ..line_task_of(8) };
+        assert_eq!(NodeManager::find_delegate(&task, &free_children, &[]), Some(3));
+    }
+
+    #[test]
+    fn partial_tasks_run_on_disjoint_children() {
+        let mut nm = node_of(1);
+        add_child_of(&mut nm, 2, 3);
+        add_child_of(&mut nm, 3, 2);
+        nm.relay_or_accept_task(&Task { uavs: 2, ..line_task_of(8) });
+        nm.relay_or_accept_task(&Task { uavs: 2, ..line_task_of(9) });
+        nm.manage_root_node_state();
+        assert_eq!(nm.get_child_task_assignments(), Some(vec![(3, 8), (2, 9)]));
+        assert!(nm.is_free() && !nm.is_idle());
+    }
+
+    #[test]
+    fn partial_task_waits_behind_task_of_whole_swarm() {
+        let mut nm = node_of(1);
+        add_child_of(&mut nm, 2, 3);
+        nm.relay_or_accept_task(&line_task_of(5));
+        nm.relay_or_accept_task(&Task { uavs: 2, ..line_task_of(8) });
+        nm.manage_root_node_state();
+        assert!(nm.has_task_of_id(5));
+        nm.children[0].desc.tsk = Some(5);  // the child follows the root node
+        nm.manage_root_node_state();
+        assert!(nm.delegations.is_empty());
+        assert!(!nm.tm.is_task_new(&line_task_of(8)));
+        // task 5 is over
+        nm.relay_or_cancel_task(5);
+        nm.children[0].desc.tsk = None;
+        nm.manage_root_node_state();
+        assert_eq!(nm.get_child_task_assignments(), Some(vec![(2, 8)]));
+    }
+
+    // root node alone, keeping station at (0, 0, 10)
+    fn root_keeping_station(policy: StationPolicy) -> NodeManager {
+        let mut nm = node_of(1);
+        let p = PosVec { x: 0.0, y: 0.0, z: 10.0 };
+        nm.relay_or_accept_task(&Task {
+            id: 5,
+            duration: Duration::from_secs(1),
+            points: vec![p],
+            station: Some(StationKeeping { threshold: 1.0, policy }),
+            ..Default::default()
+        });
+        nm.manage_root_node_state();
+        nm.p = p;
+        assert_eq!(nm.tm.ensure_task_divided(&[], 50.0, &p, nm.now), Ok(true));
+        nm.switch_state_to_in_task(5, TaskState::Success);
+        nm
+    }
+
+    fn formation_reports_of(msgs: &[Msg]) -> Vec<bool> {
+        msgs.iter().filter_map(|msg| match &msg.body {
+            MsgBody::FormationReport(report) => Some(report.degraded),
+            _ => None,
+        }).collect()
+    }
+
+    fn is_division_outdated(nm: &NodeManager) -> bool {
+        nm.tm.get_current_task().unwrap().is_division_outdated(&[])
+    }
+
+    #[test]
+    fn formation_is_reported_only_when_degraded_or_restored() {
+        let mut nm = root_keeping_station(StationPolicy::Reconverge);
+        let msgs = nm.keep_station(5);
+        assert_eq!(reports_of(&msgs), vec![(5, TaskOutcome::Success)]);
+        assert!(formation_reports_of(&msgs).is_empty());
+        nm.p.z = 12.0;
+        assert_eq!(formation_reports_of(&nm.keep_station(5)), vec![true]);
+        assert!(nm.keep_station(5).is_empty());
+        nm.now += REASSIGN_COOLDOWN;
+        assert!(nm.keep_station(5).is_empty());
+        assert!(!is_division_outdated(&nm));
+        nm.p.z = 10.5;
+        assert_eq!(formation_reports_of(&nm.keep_station(5)), vec![false]);
+        assert!(nm.keep_station(5).is_empty());
+    }
+
+    #[test]
+    fn degraded_formation_is_reassigned_after_each_cooldown() {
+        let mut nm = root_keeping_station(StationPolicy::Reassign);
+        nm.keep_station(5);
+        nm.p.z = 12.0;
+        assert_eq!(formation_reports_of(&nm.keep_station(5)), vec![true]);
+        assert!(is_division_outdated(&nm));
+        // the re-division has not restored the formation
+        assert_eq!(nm.tm.ensure_task_divided(&[], 50.0, &nm.p, nm.now), Ok(true));
+        assert!(nm.keep_station(5).is_empty());
+        assert!(!is_division_outdated(&nm));
+        nm.now += REASSIGN_COOLDOWN;
+        assert!(nm.keep_station(5).is_empty());
+        assert!(is_division_outdated(&nm));
+        // restored, no more re-division
+        nm.tm.ensure_task_divided(&[], 50.0, &nm.p, nm.now).unwrap();
+        nm.p.z = 10.0;
+        assert_eq!(formation_reports_of(&nm.keep_station(5)), vec![false]);
+        nm.now += REASSIGN_COOLDOWN;
+        nm.keep_station(5);
+        assert!(!is_division_outdated(&nm));
+    }
+
+    #[test]
+    fn cancellation_is_relayed_to_root() {
+        let mut nm = grandchild();
+        let msgs = nm.relay_or_cancel_task(5);
+        assert!(msgs.len() == 1 && msgs[0].to_ids == vec![2] && matches!(msgs[0].body, MsgBody::CancelTask(5)));
+    }
+
+    #[test]
+    fn task_too_big_for_swarm_waits_for_it_to_grow() {
+        let mut nm = node_of(1);
+        assert!(nm.relay_or_accept_task(&line_task_of(7)).is_empty());  // deferred, not rejected
+        assert!(!nm.tm.has_queued_task());
+        assert!(!nm.tm.is_task_new(&line_task_of(7)));
+        assert!(reports_of(&nm.manage_root_node_state()).is_empty());
+        assert!(nm.is_free());
+        add_child_of(&mut nm, 2, 1);
+        nm.manage_root_node_state();
+        assert!(nm.has_task_of_id(7));
+    }
+
+    #[test]
+    fn task_invalid_for_any_swarm_is_rejected() {
+        let mut nm = node_of(1);
+        let mut task = line_task_of(7);
+        task.lines[0].points[1].x = 0.5;  // too short for two uavs
+        let msgs = nm.relay_or_accept_task(&task);
+        assert_eq!(reports_of(&msgs), vec![(7, TaskOutcome::Rejected)]);
+        add_child_of(&mut nm, 2, 1);
+        nm.manage_root_node_state();
+        assert!(nm.is_free());
+        assert!(nm.relay_or_accept_task(&task).is_empty());  // a copy is not reported again
+    }
+}
@@ -4,51 +4,319 @@ use std::time::{Duration, Instant};
 
 use super::super::kinetics::{distance, PosVec, Velocity};
 
-use super::msg::{Line, Task};
+use super::msg::{Coverage, FailureReason, Keyframe, Lane, Line, PointPolicy, Target, Task, TaskError, TaskReplica};
+use super::msg::Waypoint;
 
 pub const DEFAULT_POS_MAINTAIN_PRECISION: f32 = 0.3;
 pub const DEFAULT_MAX_V_USED_RATIO: f32 = 0.8;
 pub const DEFAULT_FLY_TO_TARGET_TIMESCALE: Duration = Duration::from_millis(1000);
+pub const DEFAULT_FORMATION_TOLERANCE: f32 = 1.0;
+const TRAVEL_IMPROVEMENT_EPS: f32 = 0.01;  // m, least decrease of travel for children to be reordered
+const HEADING_MIN_SPEED: f32 = 0.1;  // m/s, below which the heading of a target is kept as it was
+
+// own position of the uav in a shape of a choreography
+pub struct KeyPos {
+    pub p: PosVec,
+    pub transition: Duration,
+    pub hold: Duration,
+}
+
+// frame of the shapes of a tracking task, following the target.
+// between updates the target is taken to move on at its last velocity.
+#[derive(Clone, Copy)]
+pub struct TargetFrame {
+    target: Target,
+    recv_t: Instant,
+    yaw: f32,  // rad, heading of the target, 0 if the shapes do not turn with it
+}
+
+impl TargetFrame {
+    fn calc_origin(&self, now: Instant) -> PosVec {
+        self.target.p + self.target.v * now.saturating_duration_since(self.recv_t)
+    }
+
+    // position of the target as of now, to be passed down the tree
+    pub fn extrapolate(&self, now: Instant) -> Target {
+        Target { p: self.calc_origin(now), ..self.target }
+    }
+
+    fn rotate(&self, x: f32, y: f32) -> (f32, f32) {
+        let (sin, cos) = self.yaw.sin_cos();
+        (cos * x - sin * y, sin * x + cos * y)
+    }
+
+    fn map_to_world(&self, p: &PosVec, now: Instant) -> PosVec {
+        let (x, y) = self.rotate(p.x, p.y);
+        self.calc_origin(now) + PosVec { x, y, z: p.z }
+    }
+
+    fn map_to_local(&self, p: &PosVec, now: Instant) -> PosVec {
+        let d = p - self.calc_origin(now);
+        let (sin, cos) = self.yaw.sin_cos();
+        PosVec { x: cos * d.x + sin * d.y, y: -sin * d.x + cos * d.y, z: d.z }
+    }
+
+    // velocity in the world of a point moving in the frame
+    fn map_velocity_to_world(&self, v: &Velocity) -> Velocity {
+        let (vx, vy) = self.rotate(v.vx, v.vy);
+        self.target.v + Velocity { vx, vy, vz: v.vz }
+    }
+}
+
+// lawnmower sweep of the own lanes of a coverage task, each lane oriented to start near the end of the previous one
+struct Sweep {
+    lanes: Vec<Lane>,
+    speed: f32,
+    idx: usize,  // lane being swept or flown to
+    progress: Option<f32>,  // m swept along the lane, None while flying to its start
+    last_t: Option<Instant>,
+    covered: Vec<u32>,  // ids of the lanes swept to the end
+}
 
 // executor monitors whether the uav is on assigned target position.
+// for a choreography, after the initial shape has been formed, the target moves through the keyframes,
+// timed by the choreography clock shared down the tree.
+// for a moving formation, the target moves with the shape frame along the path on the same clock,
+// and the velocity of the frame is fed forward.
+// the uav fails if it does not reach its position, or the task does not succeed, before the deadlines,
+// or if it falls out of the moving formation.
+// for a coverage task, the target runs along the own lanes first, and moves on only while the uav keeps up.
+// for a tracking task, all of this happens in the frame of the target, which is updated without restarting.
 pub struct TaskExecutor {
-    pos_target: PosVec,
+    pos_target: PosVec,  // current target position
+    pos_init: PosVec,  // own position in the initial shape
+    keyframes: Vec<KeyPos>,
+    path: Vec<Waypoint>,
+    tolerance: f32,
+    v_frame: Velocity,  // current velocity of the shape frame
+    sweep: Option<Sweep>,
+    frame: Option<TargetFrame>,
+    clk_start: Option<Instant>,  // when the choreography started playing, None if not yet
     fly_to_target_timescale: Duration,
     max_v_used_ratio: f32,
     on_pos_t: Option<Instant>,
     succ_duration: Duration,
+    reach_deadline: Option<Instant>,  // cleared once the position is reached
+    task_deadline: Option<Instant>,
+    failure: Option<FailureReason>,
 }
 
 impl TaskExecutor {
-    pub fn new(pos_target: &PosVec, succ_duration: Duration) -> TaskExecutor {
+    pub fn new(pos_init: &PosVec, keyframes: Vec<KeyPos>, path: Vec<Waypoint>, tolerance: Option<f32>,
+               succ_duration: Duration, reach_deadline: Option<Instant>, task_deadline: Option<Instant>)
+    -> TaskExecutor {
         TaskExecutor {
-            pos_target: *pos_target,
+            pos_target: *pos_init,
+            pos_init: *pos_init,
+            keyframes,
+            path,
+            tolerance: tolerance.unwrap_or(DEFAULT_FORMATION_TOLERANCE),
+            v_frame: Velocity::zero(),
+            sweep: None,
+            frame: None,
+            clk_start: None,
             fly_to_target_timescale: DEFAULT_FLY_TO_TARGET_TIMESCALE,
             max_v_used_ratio: DEFAULT_MAX_V_USED_RATIO,
             on_pos_t: None,
             succ_duration,
+            reach_deadline,
+            task_deadline,
+            failure: None,
+        }
+    }
+
+    pub fn get_failure(&self) -> Option<FailureReason> {
+        self.failure
+    }
+
+    // sweep the lanes in the given order before holding the position
+    pub fn set_sweep(&mut self, lanes: Vec<Lane>, speed: f32) {
+        self.sweep = Some(Sweep { lanes, speed, idx: 0, progress: None, last_t: None, covered: vec![] });
+    }
+
+    pub fn set_frame(&mut self, frame: TargetFrame) {
+        self.frame = Some(frame);
+    }
+
+    // ids of the own lanes covered so far
+    pub fn get_covered(&self) -> &[u32] {
+        self.sweep.as_ref().map_or(&[], |sw| &sw.covered)
+    }
+
+    // whether the task plays on after the initial shape, through keyframes or along a path
+    #[inline]
+    pub fn has_motion(&self) -> bool { !self.keyframes.is_empty() || !self.path.is_empty() }
+
+    #[inline]
+    pub fn is_playing(&self) -> bool { self.clk_start.is_some() }
+
+    pub fn get_clock_start(&self) -> Option<Instant> {
+        self.clk_start
+    }
+
+    pub fn set_clock_start(&mut self, clk_start: Instant) {
+        self.clk_start = Some(clk_start);
+    }
+
+    // choreography time
+    pub fn get_clock(&self, now: Instant) -> Option<Duration> {
+        self.clk_start.map(|t0| now.saturating_duration_since(t0))
+    }
+
+    // follow the choreography clock of the parent node
+    pub fn sync_clock(&mut self, clk: Duration, now: Instant) {
+        if self.has_motion() {
+            self.clk_start = now.checked_sub(clk);
+        }
+    }
+
+    // out: target position at choreography time `t`, and whether the choreography has ended
+    fn calc_choreography_target(&self, mut t: Duration) -> (PosVec, bool) {
+        let mut prev = self.pos_init;
+        for kp in &self.keyframes {
+            if t < kp.transition {  // moving straight from the previous point
+                let ratio = t.as_secs_f32() / kp.transition.as_secs_f32();
+                return (prev * (1.0 - ratio) + kp.p * ratio, false);
+            }
+            t -= kp.transition;
+            if t < kp.hold {
+                return (kp.p, false);
+            }
+            t -= kp.hold;
+            prev = kp.p;
+        }
+        (prev, true)
+    }
+
+    // out: offset and velocity of the shape frame at choreography time `t`, and whether the path has ended
+    fn calc_frame_motion(&self, t: Duration) -> (PosVec, Velocity, bool) {
+        let mut t = t.as_secs_f32();
+        let mut prev = PosVec::zero();
+        for wp in &self.path {
+            let leg = wp.offset - prev;
+            let leg_t = leg.norm() / wp.speed;
+            if t < leg_t {
+                return (prev + leg * (t / leg_t), leg / Duration::from_secs_f32(leg_t), false);
+            }
+            t -= leg_t;
+            prev = wp.offset;
         }
+        (prev, Velocity::zero(), true)
     }
 
-    pub fn calc_task_velocity(&self, pos: &PosVec, max_v: f32) -> Velocity {
-        let v = (self.pos_target - pos) / self.fly_to_target_timescale;
+    pub fn calc_task_velocity(&self, pos: &PosVec, max_v: f32, now: Instant) -> Velocity {
+        let (pos_target, v_frame) = match &self.frame {
+            Some(frame) => (frame.map_to_world(&self.pos_target, now), frame.map_velocity_to_world(&self.v_frame)),
+            None => (self.pos_target, self.v_frame),
+        };
+        let v = (pos_target - pos) / self.fly_to_target_timescale + v_frame;
         let max_v_used = max_v * self.max_v_used_ratio;
         v.get_norm_limited(max_v_used)
     }
 
+    // out: whether the sweep goes on
+    fn advance_sweep(&mut self, pos: &PosVec, now: Instant) -> bool {
+        let sweep = match self.sweep.as_mut() {
+            Some(sw) => sw,
+            None => { return false; },
+        };
+        let dt = sweep.last_t.map_or(0.0, |t| (now - t).as_secs_f32());
+        sweep.last_t = Some(now);
+        while let Some(lane) = sweep.lanes.get(sweep.idx) {
+            let len = lane.calc_length();
+            let swept = match sweep.progress {
+                None => {  // flying to the start of the lane
+                    self.pos_target = lane.a;
+                    self.v_frame = Velocity::zero();
+                    if distance(pos, &lane.a) > DEFAULT_POS_MAINTAIN_PRECISION {
+                        return true;
+                    }
+                    0.0
+                },
+                Some(swept) if distance(pos, &self.pos_target) <= self.tolerance => (swept + sweep.speed * dt).min(len),
+                Some(swept) => swept,  // waiting for the uav to catch up
+            };
+            sweep.progress = Some(swept);
+            self.pos_target = lane.a + (lane.b - lane.a) * (swept / len);
+            if swept < len {
+                self.v_frame = (lane.b - lane.a) / Duration::from_secs_f32(len / sweep.speed);
+                return true;
+            }
+            self.v_frame = Velocity::zero();
+            if distance(pos, &lane.b) > DEFAULT_POS_MAINTAIN_PRECISION {
+                return true;
+            }
+            sweep.covered.push(lane.id);
+            sweep.idx += 1;
+            sweep.progress = None;
+        }
+        false
+    }
+
     pub fn advance(&mut self, pos: &PosVec, now: Instant) -> Option<bool> {
-        if distance(pos, &self.pos_target) <= DEFAULT_POS_MAINTAIN_PRECISION {
-            if self.on_pos_t.is_none() {
-                self.on_pos_t = Some(now);
+        if self.failure.is_some() {
+            return Some(false);
+        }
+        let pos = &self.frame.map_or(*pos, |frame| frame.map_to_local(pos, now));
+        if self.advance_sweep(pos, now) {
+            self.on_pos_t = None;
+            self.check_deadlines(distance(pos, &self.pos_target) <= DEFAULT_POS_MAINTAIN_PRECISION, now);
+            return self.failure.map(|_| false);
+        }
+        let on_pos: bool;
+        let result = if let Some(clk) = self.get_clock(now) {
+            let (target, kf_ended) = self.calc_choreography_target(clk);
+            let (offset, v_frame, path_ended) = self.calc_frame_motion(clk);
+            self.pos_target = target + offset;
+            self.v_frame = v_frame;
+            let deviation = distance(pos, &self.pos_target);
+            on_pos = deviation <= DEFAULT_POS_MAINTAIN_PRECISION;
+            if !path_ended && deviation > self.tolerance {
+                self.failure = Some(FailureReason::FormationLost);
             }
+            if kf_ended && path_ended && on_pos { Some(true) } else { None }
         } else {
-            self.on_pos_t = None;
+            on_pos = distance(pos, &self.pos_target) <= DEFAULT_POS_MAINTAIN_PRECISION;
+            if on_pos {
+                if self.on_pos_t.is_none() {
+                    self.on_pos_t = Some(now);
+                }
+            } else {
+                self.on_pos_t = None;
+            }
+            self.get_result(now)
+        };
+        if result.is_none() {
+            self.check_deadlines(on_pos, now);
+        } else if result == Some(true) {
+            // deadlines are for succeeding, a formation kept afterwards is not bound to them
+            self.reach_deadline = None;
+            self.task_deadline = None;
+        }
+        result.or(self.failure.map(|_| false))
+    }
+
+    fn check_deadlines(&mut self, on_pos: bool, now: Instant) {
+        if on_pos {
+            self.reach_deadline = None;
+        }
+        if self.reach_deadline.is_some_and(|t| now > t) {
+            self.failure = Some(FailureReason::ReachTimeout);
+        } else if self.task_deadline.is_some_and(|t| now > t) {
+            self.failure = Some(FailureReason::TaskTimeout);
         }
-        self.get_result(now)
+    }
+
+    // distance of the uav from its current target position
+    pub fn calc_pos_error(&self, pos: &PosVec, now: Instant) -> f32 {
+        let pos = self.frame.map_or(*pos, |frame| frame.map_to_local(pos, now));
+        distance(&pos, &self.pos_target)
     }
 
     pub fn get_result(&self, now: Instant) -> Option<bool> {
-        // result is never failure
+        if self.failure.is_some() {
+            return Some(false);  // result is failure, a deadline has passed
+        }
         match self.on_pos_t {
             Some(on_pos_start_t) => {
                 if now - on_pos_start_t >= self.succ_duration {
@@ -62,74 +330,459 @@ impl TaskExecutor {
     }
 }
 
+#[derive(Clone)]
 pub struct ChildInfo {
     pub id: u32,
     pub subswm_size: u32,
+    pub ctr: PosVec,  // centroid of the subswarm
+}
+
+// segment of the shape of a task given to a child subswarm
+#[derive(Clone)]
+enum ShapeGroup {
+    Lines(Vec<Line>),
+    Points(Vec<PosVec>),
+    Lanes(Vec<Lane>),
+}
+
+impl ShapeGroup {
+    fn is_empty(&self) -> bool {
+        match self {
+            ShapeGroup::Lines(lines) => lines.is_empty(),
+            ShapeGroup::Points(points) => points.is_empty(),
+            ShapeGroup::Lanes(lanes) => lanes.is_empty(),
+        }
+    }
+
+    // centroid of the vertices, None if the group is empty
+    fn calc_centroid(&self) -> Option<PosVec> {
+        let points: Vec<&PosVec> = match self {
+            ShapeGroup::Lines(lines) => lines.iter().flat_map(|l| l.points.iter()).collect(),
+            ShapeGroup::Points(points) => points.iter().collect(),
+            ShapeGroup::Lanes(lanes) => lanes.iter().flat_map(|l| [&l.a, &l.b]).collect(),
+        };
+        if points.is_empty() {
+            return None;
+        }
+        Some(points.iter().fold(PosVec::zero(), |c, p| c + *p) / points.len() as f32)
+    }
 }
 
 // divider is responsible for dividing task into subtasks.
 // subtasks include: a target position for this uav, tasks for children of this uav.
 pub struct TaskDivider {
     task: Task,
+    recv_t: Instant,  // when the task was received, the task deadline counts from here
+    div: u32,  // revision of the current division, 0 if not divided
+    children_info: Vec<ChildInfo>,  // children the current division is made for, in the order of the division
     own_subtask: Option<TaskExecutor>,
     child_subtask: HashMap<u32, Task>,
+    covered: HashSet<u32>,  // lanes of a coverage task covered by the subswarm, left out of re-division
+    frame: Option<TargetFrame>,  // frame of a tracking task
+    outdated: bool,  // whether the division has been given up, e.g. to re-assign a degraded formation
 }
 
 impl TaskDivider {
-    pub fn new(task: Task) -> TaskDivider {
+    // the shapes are placed in the world before division, subtasks carry world coordinates.
+    // the area of a coverage task is cut into lanes by the top node.
+    pub fn new(mut task: Task, now: Instant) -> TaskDivider {
+        let _ = task.apply_transform();  // on failure the transform is kept, and division fails
+        if let Some(cov) = task.coverage.as_mut().filter(|cov| cov.lanes.is_empty()) {
+            cov.lanes = cov.generate_lanes();
+        }
+        let frame = task.tracking.as_ref().map(|trk| TargetFrame {
+            target: Target { tid: task.id, seq: 0, p: trk.target, v: Velocity::zero() },
+            recv_t: now,
+            yaw: 0.0,
+        });
         TaskDivider {
             task,
+            recv_t: now,
+            div: 0,
+            children_info: vec![],
             own_subtask: None,
             child_subtask: HashMap::new(),
+            covered: HashSet::new(),
+            frame,
+            outdated: false,
+        }
+    }
+
+    // take a target update, from gcs at the root node or from the parent at other nodes.
+    // the own subtask follows it at once, the division is left as it is.
+    pub fn follow_target(&mut self, target: &Target, now: Instant) {
+        let (frame, heading) = match (self.frame.as_mut(), self.task.tracking.as_ref()) {
+            (Some(frame), Some(trk)) if target.tid == self.task.id && target.seq >= frame.target.seq => {
+                (frame, trk.heading)
+            },
+            _ => { return; },
+        };
+        frame.target = *target;
+        frame.recv_t = now;
+        if heading && target.v.vx.hypot(target.v.vy) >= HEADING_MIN_SPEED {
+            frame.yaw = target.v.vy.atan2(target.v.vx);
+        }
+        if let Some(te) = self.own_subtask.as_mut() {
+            te.set_frame(*frame);
         }
     }
 
+    pub fn get_target(&self, now: Instant) -> Option<Target> {
+        self.frame.map(|frame| frame.extrapolate(now))
+    }
+
     pub fn get_tid(&self) -> u32 {
         self.task.id
     }
 
-    pub fn divide_task(&mut self, children_info: &Vec<ChildInfo>, comm_range: f32) {
-        let (pos_own, line_groups) = self.divide_pos_own_and_line_groups(&children_info);
+    pub fn get_task(&self) -> &Task {
+        &self.task
+    }
+
+    pub fn get_div(&self) -> u32 {
+        self.div
+    }
+
+    // out: whether any lane is newly covered
+    pub fn mark_covered<'a>(&mut self, lanes: impl IntoIterator<Item = &'a u32>) -> bool {
+        let num = self.covered.len();
+        self.covered.extend(lanes);
+        self.covered.len() > num
+    }
+
+    pub fn get_covered(&self) -> Vec<u32> {
+        let mut covered: Vec<u32> = self.covered.iter().copied().collect();
+        covered.sort();
+        covered
+    }
+
+    // fraction of the lanes of a coverage task covered, by length
+    pub fn calc_coverage(&self) -> Option<f32> {
+        let cov = self.task.coverage.as_ref()?;
+        let total: f32 = cov.lanes.iter().map(|l| l.calc_length()).sum();
+        let covered: f32 = cov.lanes.iter().filter(|l| self.covered.contains(&l.id)).map(|l| l.calc_length()).sum();
+        Some(if total > 0.0 { covered / total } else { 1.0 })
+    }
+
+    // the division is outdated if children have changed, or their subswarm sizes have changed,
+    // or if it has been given up. moving centroids do not outdate the division.
+    pub fn is_division_outdated(&self, children_info: &[ChildInfo]) -> bool {
+        let sizes = |children_info: &[ChildInfo]| {
+            let mut sizes: Vec<(u32, u32)> = children_info.iter().map(|ci| (ci.id, ci.subswm_size)).collect();
+            sizes.sort();
+            sizes
+        };
+        self.is_task_divided() && (self.outdated || sizes(&self.children_info) != sizes(children_info))
+    }
+
+    // once the task has succeeded, a later re-division is not bound to the task deadline any more.
+    // the deadline is dropped from the subtasks of children as well.
+    pub fn clear_task_deadline(&mut self) {
+        self.task.task_timeout = None;
+    }
+
+    // the task is divided again from the current positions, a choreography keeps its clock
+    pub fn outdate_division(&mut self) {
+        self.outdated = true;
+    }
+
+    pub fn clear_division(&mut self) {
+        self.outdated = false;
+        self.div = 0;
+        self.children_info.clear();
+        self.own_subtask = None;
+        self.child_subtask.clear();
+    }
+
+    // every shape of a choreography is divided for the same children in the same order,
+    // so each subswarm keeps its segment of the formation from one shape to the next.
+    // the division is left unchanged on error.
+    // in: pos, current position of this uav, where it parks if it has no part in the shape.
+    pub fn divide_task(&mut self, children_info: &[ChildInfo], comm_range: f32, pos: &PosVec, div: u32,
+        now: Instant) -> Result<(), TaskError> {
+        if self.task.transform.is_some() {
+            return Err(TaskError::UnresolvedAnchor);
+        }
+        // a tracking task is divided in the frame of the target
+        let pos = &self.frame.map_or(*pos, |frame| frame.map_to_local(pos, now));
+        let children_info: Vec<ChildInfo> = children_info.iter().map(|ci| ChildInfo {
+            ctr: self.frame.map_or(ci.ctr, |frame| frame.map_to_local(&ci.ctr, now)),
+            ..ci.clone()
+        }).collect();
+        let children_info = &self.order_children(&children_info)?;
+        let (pos_own, shape_groups) = self.divide_shape(children_info)?;
+        let pos_own = pos_own.unwrap_or(*pos);
         if let Some(comm_pos) = &self.task.comm_point {
             // top node should not lose contact with its parent.
-            // TODO: fail task rather than panic.
-            assert!(distance(&pos_own, comm_pos) < comm_range);
+            if distance(&pos_own, comm_pos) >= comm_range {
+                return Err(TaskError::OutOfCommRange);
+            }
         }
-        self.own_subtask = Some(TaskExecutor::new(&pos_own, self.task.duration));
-        for (cinfo, line_grp) in children_info.iter().zip(line_groups.into_iter()) {
+        let mut own_keyframes: Vec<KeyPos> = vec![];
+        let mut child_keyframes: Vec<Vec<Keyframe>> = vec![vec![]; children_info.len()];
+        for kf in &self.task.keyframes {
+            let (p, kf_groups) = Self::divide_pos_own_and_line_groups(&kf.lines, children_info)?;
+            own_keyframes.push(KeyPos {
+                p,
+                transition: kf.transition,
+                hold: kf.hold,
+            });
+            for (ckfs, kf_grp) in child_keyframes.iter_mut().zip(kf_groups) {
+                ckfs.push(Keyframe {
+                    lines: kf_grp,
+                    transition: kf.transition,
+                    hold: kf.hold,
+                });
+            }
+        }
+        self.clear_division();
+        self.div = div;
+        self.children_info = children_info.to_vec();
+        let reach_deadline = self.task.reach_timeout.map(|timeout| now + timeout);
+        let task_deadline = self.task.task_timeout.map(|timeout| self.recv_t + timeout);
+        let mut own_subtask = TaskExecutor::new(&pos_own, own_keyframes, self.task.path.clone(),
+            self.task.tolerance, self.task.duration, reach_deadline, task_deadline);
+        if let Some(cov) = &self.task.coverage {
+            own_subtask.set_sweep(Self::plan_sweep(self.divide_lanes(cov, children_info).0), cov.speed);
+        }
+        if let Some(frame) = self.frame {
+            own_subtask.set_frame(frame);
+        }
+        self.own_subtask = Some(own_subtask);
+        for ((cinfo, shape_grp), ckfs) in children_info.iter().zip(shape_groups).zip(child_keyframes) {
+            let park = shape_grp.is_empty();
+            let (lines, points, coverage) = match shape_grp {
+                ShapeGroup::Lines(lines) => (lines, vec![], None),
+                ShapeGroup::Points(points) => (vec![], points, None),
+                ShapeGroup::Lanes(_) if park => (vec![], vec![], None),
+                ShapeGroup::Lanes(lanes) => (vec![], vec![], self.task.coverage.clone().map(|cov| Coverage { lanes, ..cov })),
+            };
             self.child_subtask.insert(cinfo.id, Task {
                 id: self.task.id,
-                lines: line_grp,
+                lines,
                 duration: self.task.duration,
-                comm_point: Some(pos_own.clone()),
+                // sweeping uavs range over their whole band, the position of the parent is no bound
+                comm_point: if self.task.coverage.is_some() { None } else { Some(pos_own.clone()) },
+                priority: self.task.priority,
+                requeue: self.task.requeue,
+                div,
+                uavs: 0,
+                subtree: None,
+                keyframes: ckfs,
+                points,
+                point_policy: self.task.point_policy,
+                park,
+                reach_timeout: self.task.reach_timeout,
+                task_timeout: self.task.task_timeout,
+                transform: None,
+                path: self.task.path.clone(),
+                tolerance: self.task.tolerance,
+                coverage,
+                tracking: self.task.tracking.clone(),
+                station: None,  // only the root keeps station
             });
         }
+        Ok(())
+    }
+
+    // segments of the shape are handed to children in their order, so the order decides where each subswarm flies.
+    // children are swapped in the order as long as the total travel of the uavs from the subswarm centroids
+    // to their segments decreases.
+    fn order_children(&self, children_info: &[ChildInfo]) -> Result<Vec<ChildInfo>, TaskError> {
+        let mut order = children_info.to_vec();
+        let mut travel = self.calc_travel(&order)?;
+        let mut improved = true;
+        while improved {
+            improved = false;
+            for i in 0..order.len() {
+                for j in (i + 1)..order.len() {
+                    order.swap(i, j);
+                    let travel_swapped = self.calc_travel(&order)?;
+                    if travel_swapped < travel - TRAVEL_IMPROVEMENT_EPS {
+                        travel = travel_swapped;
+                        improved = true;
+                    } else {
+                        order.swap(i, j);
+                    }
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    // total travel of child subswarms for the division in the given order, estimated by centroids
+    fn calc_travel(&self, children_info: &[ChildInfo]) -> Result<f32, TaskError> {
+        let (_, shape_groups) = self.divide_shape(children_info)?;
+        Ok(children_info.iter().zip(shape_groups).filter_map(|(cinfo, shape_grp)| {
+            shape_grp.calc_centroid().map(|ctr| distance(&cinfo.ctr, &ctr) * cinfo.subswm_size as f32)
+        }).sum())
+    }
+
+    // out: own position, None if this uav has no part in the shape; shape groups of the children.
+    fn divide_shape(&self, children_info: &[ChildInfo])
+    -> Result<(Option<PosVec>, Vec<ShapeGroup>), TaskError> {
+        if self.task.path.iter().any(|wp| wp.speed <= 0.0) {
+            return Err(TaskError::InvalidSpeed);
+        }
+        if self.task.park {
+            return Ok((None, vec![ShapeGroup::Points(vec![]); children_info.len()]));
+        }
+        if let Some(cov) = &self.task.coverage {
+            if !self.task.lines.is_empty() || !self.task.points.is_empty() {
+                return Err(TaskError::MixedShapes);
+            }
+            let (lanes_own, lane_groups) = self.divide_lanes(cov, children_info);
+            let pos_own = Self::plan_sweep(lanes_own).first().map(|l| l.a);
+            return Ok((pos_own, lane_groups.into_iter().map(ShapeGroup::Lanes).collect()));
+        }
+        if self.task.points.is_empty() {
+            let (pos_own, line_groups) = Self::divide_pos_own_and_line_groups(&self.task.lines, children_info)?;
+            return Ok((Some(pos_own), line_groups.into_iter().map(ShapeGroup::Lines).collect()));
+        }
+        if !self.task.lines.is_empty() {
+            return Err(TaskError::MixedShapes);
+        }
+        let (pos_own, point_groups) = Self::divide_pos_own_and_point_groups(&self.task.points,
+            self.task.point_policy, children_info)?;
+        Ok((pos_own, point_groups.into_iter().map(ShapeGroup::Points).collect()))
+    }
+
+    // lanes not yet covered are cut into consecutive bands, each group getting a share of the total length
+    // in proportion to its size. a lane goes to the band its midpoint falls in.
+    // out: own lanes, lane groups of the children.
+    fn divide_lanes(&self, cov: &Coverage, children_info: &[ChildInfo]) -> (Vec<Lane>, Vec<Vec<Lane>>) {
+        let mut grp_sizes: Vec<u32> = vec![1];
+        for cinfo in children_info {
+            grp_sizes.push(cinfo.subswm_size);
+        }
+        let subswm_size = grp_sizes.iter().sum::<u32>() as f32;
+        let lanes: Vec<&Lane> = cov.lanes.iter().filter(|l| !self.covered.contains(&l.id)).collect();
+        let total_len: f32 = lanes.iter().map(|l| l.calc_length()).sum();
+        let mut lane_groups: Vec<Vec<Lane>> = vec![vec![]; grp_sizes.len()];
+        let mut grp_idx = 0;
+        let mut grp_end = total_len * grp_sizes[0] as f32 / subswm_size;
+        let mut len: f32 = 0.0;
+        for lane in lanes {
+            let lane_len = lane.calc_length();
+            while len + lane_len / 2.0 > grp_end && grp_idx < grp_sizes.len() - 1 {
+                grp_idx += 1;
+                grp_end += total_len * grp_sizes[grp_idx] as f32 / subswm_size;
+            }
+            lane_groups[grp_idx].push(lane.clone());
+            len += lane_len;
+        }
+        let lanes_own = lane_groups.remove(0);
+        (lanes_own, lane_groups)
+    }
+
+    // each lane is swept from the end nearer to where the previous one ended
+    fn plan_sweep(mut lanes: Vec<Lane>) -> Vec<Lane> {
+        for i in 1..lanes.len() {
+            let end = lanes[i - 1].b;
+            let lane = &mut lanes[i];
+            if distance(&end, &lane.b) < distance(&end, &lane.a) {
+                (lane.a, lane.b) = (lane.b, lane.a);
+            }
+        }
+        lanes
+    }
+
+    fn divide_pos_own_and_point_groups(points: &[PosVec], policy: PointPolicy, children_info: &[ChildInfo])
+    -> Result<(Option<PosVec>, Vec<Vec<PosVec>>), TaskError> {
+        let mut grp_sizes: Vec<u32> = vec![1];
+        for cinfo in children_info {
+            grp_sizes.push(cinfo.subswm_size);
+        }
+        let subswm_size = grp_sizes.iter().sum::<u32>() as usize;
+        let points = if points.len() <= subswm_size { points.to_vec() } else {
+            match policy {
+                PointPolicy::Fail => { return Err(TaskError::TooFewUavs); },
+                PointPolicy::Subsample => Self::subsample_points(points.to_vec(), subswm_size),
+            }
+        };
+        let mut point_groups = Self::split_points(points, &grp_sizes);
+        let point_grp_own = point_groups.remove(0);
+        if point_grp_own.len() > 1 {  // this uav alone takes at most one point
+            return Err(TaskError::Indivisible);
+        }
+        Ok((point_grp_own.first().copied(), point_groups))
+    }
+
+    // split points into groups for uav groups of given sizes, by recursive k-d splits on the widest axis.
+    // each group gets a share of points in proportion to its size, never more points than its size
+    // if there are no more points than uavs in total.
+    fn split_points(mut points: Vec<PosVec>, grp_sizes: &[u32]) -> Vec<Vec<PosVec>> {
+        if grp_sizes.len() == 1 {
+            return vec![points];
+        }
+        let total = grp_sizes.iter().sum::<u32>();
+        let mut mid = 1;
+        let mut left = grp_sizes[0];
+        while mid < grp_sizes.len() - 1 && left * 2 < total {
+            left += grp_sizes[mid];
+            mid += 1;
+        }
+        let num_left = (points.len() as f32 * left as f32 / total as f32).round() as usize;
+        let axis = Self::find_widest_axis(&points);
+        points.sort_by(|a, b| axis(a).total_cmp(&axis(b)));
+        let points_right = points.split_off(num_left);
+        let mut groups = Self::split_points(points, &grp_sizes[..mid]);
+        groups.append(&mut Self::split_points(points_right, &grp_sizes[mid..]));
+        groups
     }
 
-    fn divide_pos_own_and_line_groups(&self, children_info: &Vec<ChildInfo>) -> (PosVec, Vec<Vec<Line>>) {
+    fn find_widest_axis(points: &[PosVec]) -> fn(&PosVec) -> f32 {
+        let axes: [fn(&PosVec) -> f32; 3] = [|p| p.x, |p| p.y, |p| p.z];
+        let extent = |axis: &fn(&PosVec) -> f32| {
+            let min = points.iter().map(axis).fold(f32::INFINITY, f32::min);
+            let max = points.iter().map(axis).fold(f32::NEG_INFINITY, f32::max);
+            max - min
+        };
+        axes.into_iter().max_by(|a, b| extent(a).total_cmp(&extent(b))).unwrap()
+    }
+
+    // pick num points evenly over space: split into num groups, and take the point nearest the centroid of each.
+    fn subsample_points(points: Vec<PosVec>, num: usize) -> Vec<PosVec> {
+        Self::split_points(points, &vec![1; num]).into_iter().map(|grp| {
+            let centroid = grp.iter().fold(PosVec::zero(), |c, p| c + p) / grp.len() as f32;
+            *grp.iter().min_by(|a, b| distance(a, &centroid).total_cmp(&distance(b, &centroid))).unwrap()
+        }).collect()
+    }
+
+    fn divide_pos_own_and_line_groups(lines: &[Line], children_info: &[ChildInfo])
+    -> Result<(PosVec, Vec<Vec<Line>>), TaskError> {
+        if lines.is_empty() {
+            return Err(TaskError::NoLines);
+        }
         let subswm_size = children_info.iter().map(|ci| ci.subswm_size).sum::<u32>() + 1;
-        let distrib_vec = self.distribute_uav_to_lines(subswm_size);
+        let distrib_vec = Self::distribute_uav_to_lines(lines, subswm_size)?;
         let mut grp_sizes: Vec<u32> = vec![1];
         for cinfo in children_info {
             grp_sizes.push(cinfo.subswm_size);
         }
-        let mut line_groups = Self::divide_line_groups(&self.task.lines, &distrib_vec, &grp_sizes);
+        let mut line_groups = Self::divide_line_groups(lines, &distrib_vec, &grp_sizes)?;
+        // this uav alone forms one point of a single line
         let mut line_grp_own = line_groups.remove(0);
-        assert!(line_grp_own.len() == 1);
+        if line_grp_own.len() != 1 {
+            return Err(TaskError::Indivisible);
+        }
         let line_own = line_grp_own.remove(0);
-        assert!(!line_own.start || !line_own.end);
-        let pos_own: PosVec = if line_own.start { line_own.points.first().unwrap().clone() }
-            else if line_own.end { line_own.points.last().unwrap().clone() }
-            else { Self::divide_line(line_own, 0.5).0.points.last().unwrap().clone() };
-        (pos_own, line_groups)
+        let pos_own = match (line_own.start, line_own.end) {
+            (true, true) => { return Err(TaskError::Indivisible); },
+            (true, false) => line_own.points.first().copied(),
+            (false, true) => line_own.points.last().copied(),
+            (false, false) => Self::divide_line(line_own, 0.5)?.0.points.last().copied(),
+        };
+        Ok((pos_own.ok_or(TaskError::TooFewPoints)?, line_groups))
     }
 
-    fn distribute_uav_to_lines(&self, subswm_size: u32) -> Vec<u32> {
-        let lines = &self.task.lines;
+    fn distribute_uav_to_lines(lines: &[Line], subswm_size: u32) -> Result<Vec<u32>, TaskError> {
         let mut distrib_vec: Vec<u32> = lines.iter().map(|l| l.num_least_uavs()).collect();
         let least_uavs = distrib_vec.iter().sum();
-        assert!(subswm_size >= least_uavs);
-        let len_vec: Vec<f32> = lines.iter().map(|l| l.calc_length()).collect();
+        if subswm_size < least_uavs {
+            return Err(TaskError::TooFewUavs);
+        }
+        let len_vec: Vec<f32> = lines.iter().map(|l| l.calc_length()).collect::<Result<_, _>>()?;
         let end_points_vec: Vec<u32> = lines.iter().map(|l| l.num_end_points()).collect();
         for _ in 0..(subswm_size - least_uavs) {
             let ((distrib_max_load, _), _) = distrib_vec.iter_mut().zip(
@@ -137,27 +790,29 @@ impl TaskDivider {
                     |((distrib1, len1), ep1), ((distrib2, len2), ep2)| {
                         let effective_uavs1 = (**distrib1 as f32) - (**ep1 as f32) / 2.0;
                         let effective_uavs2 = (**distrib2 as f32) - (**ep2 as f32) / 2.0;
-                        (**len1 / effective_uavs1).partial_cmp(&(**len2 / effective_uavs2)).unwrap()
-                    }).unwrap();
+                        (**len1 / effective_uavs1).total_cmp(&(**len2 / effective_uavs2))
+                    }).unwrap();  // lines are not empty
             *distrib_max_load += 1;
         }
-        distrib_vec
+        Ok(distrib_vec)
     }
 
-    fn divide_line_groups(lines: &Vec<Line>, distrib_vec: &Vec<u32>, grp_sizes: &Vec<u32>) -> Vec<Vec<Line>> {
+    fn divide_line_groups(lines: &[Line], distrib_vec: &[u32], grp_sizes: &[u32])
+    -> Result<Vec<Vec<Line>>, TaskError> {
         let mut line_groups: Vec<Vec<Line>> = vec![];
         let mut line_grp: Vec<Line> = vec![];
         let mut line_task: Line;
         let (mut line_idx, mut line, mut distrib) = (0, None, 0);
         let (mut grp_idx, mut uavs) = (0, 0);
         while line_idx < lines.len() && grp_idx < grp_sizes.len() {
-            if distrib == 0 {  // assert!(line.is_none());
+            if distrib == 0 {  // line is None
                 (line, distrib) = (Some(lines[line_idx].clone()), distrib_vec[line_idx]);
             }
             if uavs == 0 {
                 uavs = grp_sizes[grp_idx];
             }
-            (line_task, line, distrib, uavs) = Self::split_line_for_uav_group(line.unwrap(), distrib, uavs);
+            let line_left = line.take().ok_or(TaskError::Indivisible)?;
+            (line_task, line, distrib, uavs) = Self::split_line_for_uav_group(line_left, distrib, uavs)?;
             line_grp.push(line_task);
             if distrib == 0 {
                 line_idx += 1;
@@ -168,52 +823,68 @@ impl TaskDivider {
                 line_grp = vec![];
             }
         }
-        assert!(line_idx == lines.len() && grp_idx == grp_sizes.len() && distrib == 0 && uavs == 0);
-        line_groups
+        // all lines are handed out, and all uav groups are given lines
+        if line_idx != lines.len() || grp_idx != grp_sizes.len() || distrib != 0 || uavs != 0 {
+            return Err(TaskError::Indivisible);
+        }
+        Ok(line_groups)
     }
 
     // out: task_line_split_off, left_line_part, left_distrib, left_uavs
-    fn split_line_for_uav_group(line: Line, distrib: u32, uavs: u32) -> (Line, Option<Line>, u32, u32) {
+    fn split_line_for_uav_group(line: Line, distrib: u32, uavs: u32)
+    -> Result<(Line, Option<Line>, u32, u32), TaskError> {
         if distrib <= uavs {
-            (line, None, 0, uavs - distrib)
+            Ok((line, None, 0, uavs - distrib))
         } else {
             let left_distrib = distrib - uavs;
             let weight_split: f32 = if line.start { (uavs as f32) - 0.5 } else { uavs as f32 };
             let weight_left: f32 = if line.end { (left_distrib as f32) - 0.5 } else { left_distrib as f32 };
             let ratio = weight_split / (weight_split + weight_left);
-            let (line_split, line_left) = Self::divide_line(line, ratio);
-            (line_split, Some(line_left), left_distrib, 0)
+            let (line_split, line_left) = Self::divide_line(line, ratio)?;
+            Ok((line_split, Some(line_left), left_distrib, 0))
         }
     }
 
-    fn divide_line(mut line: Line, ratio: f32) -> (Line, Line) {  // divide a line into tow by ratio
-        assert!(0.0 < ratio && ratio < 1.0);
-        let len1 = line.calc_length() * ratio;
+    fn divide_line(mut line: Line, ratio: f32) -> Result<(Line, Line), TaskError> {  // divide a line into tow by ratio
+        if !(0.0 < ratio && ratio < 1.0) {  // also nan
+            return Err(TaskError::Indivisible);
+        }
+        let len1 = line.calc_length()? * ratio;
         let mut line2 = Line {
             points: vec![],
+            curves: vec![],
             start: false,  // breakpoint does not require uav
             end: line.end,
         };
         line.end = false;  // breakpoint does not require uav
-        let mut cur_len: f32 = 0.0;
         let mut prev_len: f32 = 0.0;
-        let mut idx: usize = 0;
+        let mut idx: usize = line.points.len() - 1;
         for i in 1..line.points.len() {  // find the two points immediately adjacent to the breakpoint
-            prev_len = cur_len;
-            cur_len += distance(&line.points[i], &line.points[i - 1]);
-            if cur_len >= len1 {
+            let seg_len = line.calc_segment_length(i - 1);
+            if prev_len + seg_len >= len1 {
                 idx = i;
                 break;
             }
+            prev_len += seg_len;
         }
-        let weight1 = cur_len - len1;
-        let weight2 = len1 - prev_len;
-        let breakpoint = (line.points[idx - 1] * weight1 + line.points[idx] * weight2) / (weight1 + weight2);
-        line2.points.push(breakpoint.clone());
+        let seg_len = line.calc_segment_length(idx - 1);
+        let seg_ratio = if seg_len > 0.0 { ((len1 - prev_len) / seg_len).clamp(0.0, 1.0) } else { 0.0 };
+        let (p0, p1) = (&line.points[idx - 1], &line.points[idx]);
+        let (breakpoint, curve1, curve2) = match line.get_curve(idx - 1) {
+            Some(curve) => curve.split(p0, p1, seg_ratio),
+            None => (p0 * (1.0 - seg_ratio) + p1 * seg_ratio, None, None),
+        };
+        line2.points.push(breakpoint);
         line2.points.extend_from_slice(&line.points[idx..]);
         line.points.drain(idx..);
         line.points.push(breakpoint);
-        (line, line2)
+        if !line.curves.is_empty() {
+            line2.curves.push(curve2);
+            line2.curves.extend(line.curves.iter().skip(idx).cloned());
+            line.curves.resize(idx - 1, None);
+            line.curves.push(curve1);
+        }
+        Ok((line, line2))
     }
 
     pub fn is_task_divided(&self) -> bool {
@@ -235,16 +906,24 @@ impl TaskDivider {
 
 pub struct TaskManager {
     task_exec: Option<TaskDivider>,
+    task_src: Option<u32>,  // id of the parent which allocated the current subtask
+    div_count: u32,  // number of divisions made by this node, used as division revision
     queued_tasks: VecDeque<Task>,
+    deferred_tasks: Vec<(Task, u32)>,  // tasks too big for the swarm, with the swarm size they were last checked for
     old_tasks: HashSet<u32>,
+    replica: Option<(u32, TaskReplica)>,  // tasks replicated from the root node of given id
 }
 
 impl TaskManager {
     pub fn new() -> TaskManager {
         TaskManager {
             task_exec: None,
+            task_src: None,
+            div_count: 0,
             queued_tasks: VecDeque::<Task>::new(),
+            deferred_tasks: vec![],
             old_tasks: HashSet::<u32>::new(),
+            replica: None,
         }
     }
 
@@ -256,8 +935,54 @@ impl TaskManager {
         self.task_exec.as_mut()
     }
 
-    pub fn set_current_task(&mut self, task: Task) {
-        self.task_exec = Some(TaskDivider::new(task));
+    pub fn set_current_task(&mut self, task: Task, now: Instant) {
+        self.task_exec = Some(TaskDivider::new(task, now));
+        self.task_src = None;
+    }
+
+    pub fn set_current_subtask(&mut self, subtask: Task, pid: u32, now: Instant) {
+        self.task_exec = Some(TaskDivider::new(subtask, now));
+        self.task_src = Some(pid);
+    }
+
+    // whether the current subtask is exactly the one allocated by the parent in its latest division
+    pub fn is_current_subtask_from(&self, pid: u32, div: u32) -> bool {
+        self.task_src == Some(pid) && self.task_exec.as_ref().is_some_and(|td| td.get_task().div == div)
+    }
+
+    // revision of the division that produced the current subtask, 0 if not from the given parent
+    pub fn get_current_subtask_div_from(&self, pid: u32) -> u32 {
+        match &self.task_exec {
+            Some(td) if self.task_src == Some(pid) => td.get_task().div,
+            _ => 0,
+        }
+    }
+
+    // divide the current task, or re-divide it if the division is outdated.
+    // out: Ok(true) if the task is newly divided, Ok(false) if the division is unchanged or there is no task,
+    //      Err if the task cannot be divided, e.g. there are too few uavs for the task,
+    //      in which case the previous division, if any, is kept.
+    pub fn ensure_task_divided(&mut self, children_info: &[ChildInfo], comm_range: f32, pos: &PosVec,
+        now: Instant) -> Result<bool, TaskError> {
+        let td = match self.task_exec.as_mut() {
+            Some(td) => td,
+            None => { return Ok(false); },
+        };
+        if td.is_task_divided() && !td.is_division_outdated(children_info) {
+            return Ok(false);
+        }
+        // a choreography keeps playing on after re-division
+        let clk_start = td.get_own_subtask().and_then(|te| te.get_clock_start());
+        let subswm_size = children_info.iter().map(|ci| ci.subswm_size).sum::<u32>() + 1;
+        if subswm_size < td.get_task().num_least_uavs() {
+            return Err(TaskError::TooFewUavs);
+        }
+        td.divide_task(children_info, comm_range, pos, self.div_count + 1, now)?;
+        self.div_count += 1;
+        if let Some(t0) = clk_start {
+            td.get_own_subtask_mut().unwrap().set_clock_start(t0);
+        }
+        Ok(true)
     }
 
     pub fn clear_current_task(&mut self) {
@@ -265,27 +990,464 @@ impl TaskManager {
             Some(te) => {
                 self.old_tasks.insert(te.get_tid());
                 self.task_exec = None;
+                self.task_src = None;
             },
             None => (),
         }
     }
 
+    // the first queued task of the whole swarm
     pub fn pop_queued_task(&mut self) -> Option<Task> {
-        self.queued_tasks.pop_front()
+        let idx = self.queued_tasks.iter().position(|t| !t.is_partial())?;
+        self.queued_tasks.remove(idx)
+    }
+
+    // take the first queued partial task that can be run now, it is never accepted again.
+    // partial tasks queued behind a task of the whole swarm wait for it to start and end.
+    pub fn dispatch_partial_task(&mut self, can_run: impl Fn(&Task) -> bool) -> Option<Task> {
+        let idx = self.queued_tasks.iter().take_while(|t| t.is_partial()).position(can_run)?;
+        let task = self.queued_tasks.remove(idx)?;
+        self.old_tasks.insert(task.id);
+        Some(task)
+    }
+
+    // revision for a subtask not produced by division, e.g. a partial task delegated by the root node
+    pub fn new_div(&mut self) -> u32 {
+        self.div_count += 1;
+        self.div_count
+    }
+
+    // remove the task from the queue, and never accept it again.
+    // the current task is not touched here, it should be cleared by the caller.
+    pub fn cancel_task(&mut self, tid: u32) {
+        self.queued_tasks.retain(|t| t.id != tid);
+        self.deferred_tasks.retain(|(t, _)| t.id != tid);
+        self.old_tasks.insert(tid);
+    }
+
+    // never accept an invalid task, so that its copies are not reported again
+    pub fn reject_task(&mut self, tid: u32) {
+        self.old_tasks.insert(tid);
+    }
+
+    // keep a task too big for the swarm of the given size, until the swarm has grown enough for it
+    pub fn defer_task(&mut self, task: &Task, swarm_size: u32) {
+        if self.is_task_new(task) {
+            self.deferred_tasks.push((task.clone(), swarm_size));
+        }
+    }
+
+    // deferred tasks are checked again whenever the swarm has grown, and queued if the check passes.
+    // out: ids of the tasks queued
+    pub fn requeue_deferred_tasks(&mut self, swarm_size: u32, is_feasible: impl Fn(&Task) -> bool) -> Vec<u32> {
+        let mut requeued: Vec<Task> = vec![];
+        self.deferred_tasks.retain_mut(|(t, size)| {
+            if swarm_size <= *size {
+                return true;
+            }
+            *size = swarm_size;
+            if is_feasible(t) {
+                requeued.push(t.clone());
+                return false;
+            }
+            true
+        });
+        requeued.into_iter().map(|t| {
+            let tid = t.id;
+            self.insert_queued_task(t);
+            tid
+        }).collect()
+    }
+
+    // tasks that have ended at the lost root node are never accepted again
+    pub fn add_old_tasks(&mut self, tids: &[u32]) {
+        self.old_tasks.extend(tids);
+    }
+
+    pub fn push_front_queued_task(&mut self, task: Task) {
+        self.queued_tasks.push_front(task);
+    }
+
+    pub fn generate_replica(&self) -> TaskReplica {
+        TaskReplica {
+            task: self.task_exec.as_ref().map(|td| td.get_task().clone()),
+            queue: self.queued_tasks.iter().cloned().collect(),
+            deferred: self.deferred_tasks.iter().map(|(t, _)| t.clone()).collect(),
+            old: self.old_tasks.iter().copied().collect(),
+        }
+    }
+
+    pub fn store_replica(&mut self, root_id: u32, replica: &TaskReplica) {
+        self.replica = Some((root_id, replica.clone()));
+    }
+
+    // take the replica only if it is from the given root node
+    pub fn take_replica(&mut self, root_id: u32) -> Option<TaskReplica> {
+        match self.replica.take() {
+            Some((rid, replica)) if rid == root_id => Some(replica),
+            _ => None,
+        }
+    }
+
+    // a queued task of the whole swarm of higher priority than the current task
+    pub fn has_preempting_task(&self) -> bool {
+        match (&self.task_exec, self.queued_tasks.iter().find(|t| !t.is_partial())) {
+            (Some(td), Some(t)) => t.priority > td.get_task().priority,
+            _ => false,
+        }
+    }
+
+    // stop the current task, putting it back into the queue if it asks for that
+    pub fn preempt_current_task(&mut self) {
+        self.task_src = None;
+        if let Some(td) = self.task_exec.take() {
+            if td.task.requeue {
+                self.insert_queued_task(td.task);
+            } else {
+                self.old_tasks.insert(td.get_tid());
+            }
+        }
+    }
+
+    // tasks are queued by priority, and in order of arrival for the same priority
+    fn insert_queued_task(&mut self, task: Task) {
+        let idx = self.queued_tasks.iter().position(|t| t.priority < task.priority).unwrap_or(self.queued_tasks.len());
+        self.queued_tasks.insert(idx, task);
+    }
+
+    pub fn has_queued_task(&self) -> bool {
+        self.queued_tasks.iter().any(|t| !t.is_partial())
     }
 
     pub fn is_task_new(&self, task: &Task) -> bool {
         !self.task_exec.as_ref().is_some_and(|te| te.get_tid() == task.id)
         && !self.old_tasks.contains(&task.id)
         && self.queued_tasks.iter().all(|t| t.id != task.id)
+        && self.deferred_tasks.iter().all(|(t, _)| t.id != task.id)
     }
 
     pub fn add_task_if_new(&mut self, task: &Task) -> bool {
         if self.is_task_new(task) {
-            self.queued_tasks.push_back(task.clone());
+            self.insert_queued_task(task.clone());
             true
         } else {
             false
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line_of(points: &[(f32, f32)], start: bool, end: bool) -> Line {
+        Line {
+            points: points.iter().map(|&(x, y)| PosVec { x, y, z: 10.0 }).collect(),
+            curves: vec![],
+            start,
+            end,
+        }
+    }
+
+    fn children_of(sizes: &[u32]) -> Vec<ChildInfo> {
+        sizes.iter().enumerate().map(|(i, &subswm_size)| ChildInfo {
+            id: i as u32 + 1,
+            subswm_size,
+            ctr: PosVec::zero(),
+        }).collect()
+    }
+
+    #[test]
+    fn line_is_not_divided_at_ends() {
+        let line = line_of(&[(0.0, 0.0), (10.0, 0.0)], true, true);
+        for ratio in [0.0, 1.0, -0.5, f32::NAN] {
+            assert_eq!(TaskDivider::divide_line(line.clone(), ratio).err(), Some(TaskError::Indivisible));
+        }
+        let (line1, line2) = TaskDivider::divide_line(line, 0.25).unwrap();
+        assert_eq!(line1.points.last().unwrap().x, 2.5);
+        assert_eq!(line2.points.first().unwrap().x, 2.5);
+    }
+
+    #[test]
+    fn degenerate_lines_are_divided_without_panic() {
+        let lines = vec![
+            line_of(&[(0.0, 0.0), (0.0, 0.0)], true, true),
+            line_of(&[(0.0, 5.0), (10.0, 5.0)], true, true),
+        ];
+        for sizes in [vec![], vec![1], vec![1, 2], vec![3, 3]] {
+            let children_info = children_of(&sizes);
+            let result = TaskDivider::divide_pos_own_and_line_groups(&lines, &children_info);
+            match result {
+                Ok((_, line_groups)) => assert_eq!(line_groups.len(), sizes.len()),
+                Err(err) => assert!(matches!(err, TaskError::ZeroLength | TaskError::TooFewUavs | TaskError::Indivisible),
+                    "{:?}", err),
+            }
+        }
+    }
+
+    fn points_of(num: usize) -> Vec<PosVec> {
+        (0..num).map(|i| PosVec { x: i as f32, y: (i % 3) as f32, z: 10.0 }).collect()
+    }
+
+    #[test]
+    fn points_are_split_by_group_sizes() {
+        let groups = TaskDivider::split_points(points_of(10), &[1, 2, 3, 4]);
+        assert_eq!(groups.iter().map(|g| g.len()).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        // uneven groups, fewer points than uavs: no group gets more points than its size
+        let groups = TaskDivider::split_points(points_of(5), &[1, 4, 2, 1]);
+        assert_eq!(groups.iter().map(|g| g.len()).sum::<usize>(), 5);
+        assert!(groups.iter().zip([1, 4, 2, 1]).all(|(g, size)| g.len() <= size));
+    }
+
+    #[test]
+    fn more_uavs_than_points() {
+        let children_info = children_of(&[3, 4]);
+        for policy in [PointPolicy::Fail, PointPolicy::Subsample] {
+            let (pos_own, point_groups) =
+                TaskDivider::divide_pos_own_and_point_groups(&points_of(5), policy, &children_info).unwrap();
+            let num = pos_own.iter().count() + point_groups.iter().map(|g| g.len()).sum::<usize>();
+            assert_eq!(num, 5);
+            assert!(point_groups[0].len() <= 3 && point_groups[1].len() <= 4);
+        }
+    }
+
+    #[test]
+    fn fewer_uavs_than_points() {
+        let children_info = children_of(&[2, 3]);
+        let points = points_of(12);
+        let result = TaskDivider::divide_pos_own_and_point_groups(&points, PointPolicy::Fail, &children_info);
+        assert_eq!(result.err(), Some(TaskError::TooFewUavs));
+        let (pos_own, point_groups) =
+            TaskDivider::divide_pos_own_and_point_groups(&points, PointPolicy::Subsample, &children_info).unwrap();
+        assert!(pos_own.is_some());
+        assert_eq!(point_groups.iter().map(|g| g.len()).collect::<Vec<_>>(), vec![2, 3]);
+        let picked: Vec<PosVec> = pos_own.into_iter().chain(point_groups.into_iter().flatten()).collect();
+        assert!(picked.iter().all(|p| points.iter().any(|q| distance(p, q) == 0.0)));
+        let subsampled = TaskDivider::subsample_points(points.clone(), 4);
+        assert_eq!(subsampled.len(), 4);
+        assert!(subsampled.iter().all(|p| points.iter().any(|q| distance(p, q) == 0.0)));
+    }
+
+    fn coverage_divider() -> TaskDivider {
+        let task: Task = serde_json::from_str(r#"{
+            "id": 0,
+            "lines": [],
+            "duration": {"secs": 1, "nanos": 0},
+            "coverage": {"area": [{"x": 0, "y": 0, "z": 0}, {"x": 20, "y": 0, "z": 0},
+                                  {"x": 20, "y": 12, "z": 0}, {"x": 0, "y": 12, "z": 0}],
+                         "altitude": 10, "width": 2}
+        }"#).unwrap();
+        TaskDivider::new(task, Instant::now())
+    }
+
+    fn ids_of(lanes: &[Lane]) -> Vec<u32> {
+        lanes.iter().map(|l| l.id).collect()
+    }
+
+    #[test]
+    fn lanes_are_divided_in_bands_by_subswarm_size() {
+        let td = coverage_divider();
+        let cov = td.get_task().coverage.clone().unwrap();
+        assert_eq!(cov.lanes.len(), 6);
+        let (lanes_own, lane_groups) = td.divide_lanes(&cov, &children_of(&[2, 3]));
+        assert_eq!(ids_of(&lanes_own), vec![0]);
+        assert_eq!(lane_groups.iter().map(|g| ids_of(g)).collect::<Vec<_>>(), vec![vec![1, 2], vec![3, 4, 5]]);
+        assert_eq!(td.calc_coverage(), Some(0.0));
+    }
+
+    #[test]
+    fn covered_lanes_are_not_reassigned() {
+        let mut td = coverage_divider();
+        let cov = td.get_task().coverage.clone().unwrap();
+        assert!(td.mark_covered(&[0, 1, 3]));
+        assert!(!td.mark_covered(&[1]));
+        assert_eq!(td.get_covered(), vec![0, 1, 3]);
+        assert_eq!(td.calc_coverage(), Some(0.5));
+        // the second child is lost, the first child and this uav take over what is left
+        let (lanes_own, lane_groups) = td.divide_lanes(&cov, &children_of(&[2]));
+        let mut assigned: Vec<u32> = ids_of(&lanes_own);
+        assigned.extend(lane_groups.iter().flat_map(|g| ids_of(g)));
+        assigned.sort();
+        assert_eq!(assigned, vec![2, 4, 5]);
+        assert_eq!(lane_groups.len(), 1);
+        assert!(!lanes_own.is_empty() && !lane_groups[0].is_empty());
+        td.mark_covered(&[2, 4, 5]);
+        assert_eq!(td.calc_coverage(), Some(1.0));
+    }
+
+    #[test]
+    fn sweep_alternates_lane_directions() {
+        let td = coverage_divider();
+        let lanes = TaskDivider::plan_sweep(td.get_task().coverage.clone().unwrap().lanes);
+        for pair in lanes.windows(2) {
+            assert!(distance(&pair[0].b, &pair[1].a) < distance(&pair[0].b, &pair[1].b));
+            assert_eq!(pair[0].b.x, pair[1].a.x);
+        }
+    }
+
+    #[test]
+    fn deferred_tasks_are_checked_again_only_when_the_swarm_grows() {
+        let mut tm = TaskManager::new();
+        let task = Task { id: 3, ..Default::default() };
+        tm.defer_task(&task, 2);
+        tm.defer_task(&task, 2);
+        assert!(!tm.is_task_new(&task));
+        assert_eq!(tm.generate_replica().deferred.len(), 1);
+        let checked = std::cell::Cell::new(0);
+        let checked = &checked;
+        let check = |feasible: bool| move |_: &Task| {
+            checked.set(checked.get() + 1);
+            feasible
+        };
+        assert!(tm.requeue_deferred_tasks(2, check(true)).is_empty());
+        assert_eq!(checked.get(), 0);
+        assert!(tm.requeue_deferred_tasks(3, check(false)).is_empty());
+        assert_eq!(checked.get(), 1);
+        assert!(tm.requeue_deferred_tasks(3, check(true)).is_empty());  // no bigger than last checked
+        assert_eq!(tm.requeue_deferred_tasks(4, check(true)), vec![3]);
+        assert_eq!(tm.pop_queued_task().map(|t| t.id), Some(3));
+        tm.defer_task(&Task { id: 4, ..Default::default() }, 2);
+        tm.cancel_task(4);
+        assert!(tm.requeue_deferred_tasks(9, check(true)).is_empty());
+    }
+
+    // a line of 4 uavs at least, between (0, 0) and (30, 0)
+    fn line_manager() -> TaskManager {
+        let task = Task {
+            id: 5,
+            lines: vec![line_of(&[(0.0, 0.0), (10.0, 0.0)], true, true), line_of(&[(20.0, 0.0), (30.0, 0.0)], true, true)],
+            duration: Duration::from_secs(1),
+            comm_point: Some(PosVec { x: 15.0, y: 0.0, z: 10.0 }),
+            ..Default::default()
+        };
+        let mut tm = TaskManager::new();
+        tm.set_current_task(task, Instant::now());
+        tm
+    }
+
+    // out: revision of the division, own target, children given a subtask
+    fn division_of(tm: &TaskManager) -> (u32, PosVec, Vec<u32>) {
+        let td = tm.get_current_task().unwrap();
+        let mut cids: Vec<u32> = (1..=4).filter(|cid| td.get_child_subtask(*cid).is_some()).collect();
+        cids.sort();
+        (td.get_div(), td.get_own_subtask().unwrap().pos_target, cids)
+    }
+
+    #[test]
+    fn shrinking_subswarm_re_divides_the_task() {
+        let mut tm = line_manager();
+        let now = Instant::now();
+        assert_eq!(tm.ensure_task_divided(&children_of(&[2, 3]), 100.0, &PosVec::zero(), now), Ok(true));
+        assert_eq!(tm.ensure_task_divided(&children_of(&[2, 3]), 100.0, &PosVec::zero(), now), Ok(false));
+        let (div, _, cids) = division_of(&tm);
+        assert_eq!((div, cids), (1, vec![1, 2]));
+        // the first child is lost
+        assert_eq!(tm.ensure_task_divided(&children_of(&[2, 3])[1..], 100.0, &PosVec::zero(), now), Ok(true));
+        let (div, _, cids) = division_of(&tm);
+        assert_eq!((div, cids), (2, vec![2]));
+        let subtask = tm.get_current_task().unwrap().get_child_subtask(2).unwrap();
+        assert_eq!(subtask.div, 2);
+    }
+
+    #[test]
+    fn failed_re_division_keeps_the_previous_division() {
+        let mut tm = line_manager();
+        let now = Instant::now();
+        tm.ensure_task_divided(&children_of(&[2, 3]), 100.0, &PosVec::zero(), now).unwrap();
+        let (div, pos_own, cids) = division_of(&tm);
+        // shrinking below the least uavs of the task
+        let result = tm.ensure_task_divided(&children_of(&[1]), 100.0, &PosVec::zero(), now);
+        assert_eq!(result, Err(TaskError::TooFewUavs));
+        let (div_kept, pos_kept, cids_kept) = division_of(&tm);
+        assert_eq!((div_kept, cids_kept), (div, cids.clone()));
+        assert_eq!(distance(&pos_kept, &pos_own), 0.0);
+        // failing in the division itself
+        let result = tm.ensure_task_divided(&children_of(&[3]), 0.1, &PosVec::zero(), now);
+        assert_eq!(result, Err(TaskError::OutOfCommRange));
+        assert_eq!(division_of(&tm).0, div);
+        // a later division still gets a new revision
+        assert_eq!(tm.ensure_task_divided(&children_of(&[3]), 100.0, &PosVec::zero(), now), Ok(true));
+        assert_eq!(division_of(&tm).0, div + 1);
+    }
+
+    #[test]
+    fn cancelled_task_is_dequeued_and_never_accepted_again() {
+        let mut tm = TaskManager::new();
+        for id in [1, 2] {
+            assert!(tm.add_task_if_new(&Task { id, ..Default::default() }));
+        }
+        tm.cancel_task(1);
+        assert!(!tm.add_task_if_new(&Task { id: 1, ..Default::default() }));
+        assert_eq!(tm.pop_queued_task().map(|t| t.id), Some(2));
+        assert!(tm.pop_queued_task().is_none());
+        // an unknown task cancelled before it arrives
+        tm.cancel_task(3);
+        assert!(!tm.add_task_if_new(&Task { id: 3, ..Default::default() }));
+    }
+
+    fn prioritised_task_of(id: u32, priority: u32, requeue: bool) -> Task {
+        Task { id, priority, requeue, ..Default::default() }
+    }
+
+    fn queued_ids_of(mut tm: TaskManager) -> Vec<u32> {
+        std::iter::from_fn(|| tm.pop_queued_task()).map(|t| t.id).collect()
+    }
+
+    #[test]
+    fn tasks_are_queued_by_priority_then_arrival() {
+        let mut tm = TaskManager::new();
+        for (id, priority) in [(1, 0), (2, 2), (3, 1), (4, 2), (5, 0)] {
+            tm.add_task_if_new(&prioritised_task_of(id, priority, false));
+        }
+        assert_eq!(queued_ids_of(tm), vec![2, 4, 3, 1, 5]);
+    }
+
+    #[test]
+    fn only_higher_priority_of_whole_swarm_preempts() {
+        let mut tm = TaskManager::new();
+        tm.set_current_task(prioritised_task_of(1, 1, false), Instant::now());
+        tm.add_task_if_new(&prioritised_task_of(2, 1, false));
+        assert!(!tm.has_preempting_task());
+        tm.add_task_if_new(&Task { uavs: 2, ..prioritised_task_of(3, 5, false) });  // partial
+        assert!(!tm.has_preempting_task());
+        tm.add_task_if_new(&prioritised_task_of(4, 2, false));
+        assert!(tm.has_preempting_task());
+    }
+
+    #[test]
+    fn preempted_task_is_requeued_behind_tasks_of_same_priority() {
+        let mut tm = TaskManager::new();
+        tm.set_current_task(prioritised_task_of(1, 1, true), Instant::now());
+        for (id, priority) in [(2, 0), (3, 1), (4, 2)] {
+            tm.add_task_if_new(&prioritised_task_of(id, priority, false));
+        }
+        tm.preempt_current_task();
+        assert!(tm.get_current_task().is_none());
+        assert!(!tm.is_task_new(&prioritised_task_of(1, 1, true)));
+        assert_eq!(queued_ids_of(tm), vec![4, 3, 1, 2]);
+    }
+
+    #[test]
+    fn preempted_task_without_requeue_is_dropped() {
+        let mut tm = TaskManager::new();
+        tm.set_current_task(prioritised_task_of(1, 0, false), Instant::now());
+        tm.add_task_if_new(&prioritised_task_of(2, 1, false));
+        tm.preempt_current_task();
+        assert!(!tm.add_task_if_new(&prioritised_task_of(1, 0, false)));
+        assert_eq!(queued_ids_of(tm), vec![2]);
+    }
+
+    #[test]
+    fn relative_anchor_fails_division() {
+        let task: Task = serde_json::from_str(r#"{
+            "id": 0,
+            "lines": [{"points": [{"x": 0, "y": 0, "z": 10}, {"x": 10, "y": 0, "z": 10}], "start": true, "end": true}],
+            "duration": {"secs": 1, "nanos": 0},
+            "transform": {"anchor": {"x": 0, "y": 0, "z": 0}, "relative": true}
+        }"#).unwrap();
+        let now = Instant::now();
+        let mut td = TaskDivider::new(task, now);
+        let result = td.divide_task(&children_of(&[1]), 100.0, &PosVec::zero(), 1, now);
+        assert_eq!(result.err(), Some(TaskError::UnresolvedAnchor));
+    }
+}
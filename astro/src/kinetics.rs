@@ -27,6 +27,20 @@ pub struct Velocity {  // m/s
     pub vz: f32,
 }
 
+impl PosVec {
+    pub fn dot(&self, p: &PosVec) -> f32 {
+        self.x * p.x + self.y * p.y + self.z * p.z
+    }
+
+    pub fn cross(&self, p: &PosVec) -> PosVec {
+        PosVec {
+            x: self.y * p.z - self.z * p.y,
+            y: self.z * p.x - self.x * p.z,
+            z: self.x * p.y - self.y * p.x,
+        }
+    }
+}
+
 impl Velocity {
     pub fn limit_norm_to(&mut self, limit: f32) {
         let norm = self.norm();
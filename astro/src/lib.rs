@@ -2,6 +2,7 @@ pub mod comm;
 pub mod control;
 pub mod gps;
 pub mod kinetics;
+pub mod shape;
 pub mod transceiver;
 
 mod astro;
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use clap::Parser;
 
 use astro::{Astro, AstroConf};
@@ -15,16 +17,36 @@ struct Args {
     contact_range_ratio: f32,  // [0.0, 1.0]
     #[arg(long)]
     max_v: f32,
+    #[arg(long, default_value_t = 4)]
+    max_children: u32,
+    #[arg(long, default_value_t = 8)]
+    max_depth: u32,
+    #[arg(long, default_value_t = 0.3)]
+    parent_children_weight: f32,
+    #[arg(long, default_value_t = 0.3)]
+    parent_depth_weight: f32,
+    #[arg(long, default_value_t = 60.0)]
+    reach_timeout: f32,  // seconds
+    #[arg(long, default_value_t = 300.0)]
+    task_timeout: f32,  // seconds
 }
 
 fn main() {
     let args = Args::parse();
+    // a negative, nan or overflowing duration fails the validation, as a zero duration does
+    let secs = |s: f32| Duration::try_from_secs_f32(s).unwrap_or(Duration::ZERO);
     let conf = AstroConf {
         id: args.id,
         uav_radius: args.uav_radius,
         msg_range: args.msg_range,
         contact_range: args.msg_range * args.contact_range_ratio,
         max_v: args.max_v,
+        max_children: args.max_children,
+        max_depth: args.max_depth,
+        parent_children_weight: args.parent_children_weight,
+        parent_depth_weight: args.parent_depth_weight,
+        reach_timeout: secs(args.reach_timeout),
+        task_timeout: secs(args.task_timeout),
     };
     conf.validate().unwrap();
     let mut astro = Astro::new(conf);
@@ -0,0 +1,24 @@
+use super::kinetics::PosVec;
+
+pub mod font;
+pub mod formation;
+pub mod svg;
+
+// plane of the world that a 2d drawing is placed onto.
+// the first drawing axis maps to the first plane axis, the second (upwards) to the second.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Plane {
+    Xy,
+    Xz,
+    Yz,
+}
+
+impl Plane {
+    pub fn map(&self, a: f32, b: f32) -> PosVec {
+        match self {
+            Plane::Xy => PosVec { x: a, y: b, z: 0.0 },
+            Plane::Xz => PosVec { x: a, y: 0.0, z: b },
+            Plane::Yz => PosVec { x: 0.0, y: a, z: b },
+        }
+    }
+}
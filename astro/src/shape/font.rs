@@ -0,0 +1,173 @@
+// a built-in single-stroke font, in the style of the hershey fonts, turning text into lines of a task.
+// glyphs are drawn on a grid 4 units wide and 8 units high, with the origin at the bottom-left.
+
+use super::super::control::msg::Line;
+use super::super::kinetics::PosVec;
+use super::Plane;
+
+const GLYPH_WIDTH: f32 = 4.0;
+const GLYPH_HEIGHT: f32 = 8.0;
+const LINE_PITCH_RATIO: f32 = 1.5;  // distance between text lines, relative to letter height
+const JOINT_EPS: f32 = 1e-3;
+
+type Stroke = &'static [(f32, f32)];
+
+#[derive(Debug)]
+pub enum TextError {
+    UnsupportedChar(char),
+    NoShape,  // the text has no visible character
+}
+
+pub struct TextOptions {
+    pub height: f32,  // m, height of letters
+    pub spacing: f32,  // m, gap between letters
+    pub plane: Plane,
+    pub origin: PosVec,  // bottom-left of the first letter
+}
+
+// letters are case-insensitive, a newline starts a text line below.
+// an open stroke takes uavs at its end points unless they join another stroke of the glyph,
+// a closed stroke becomes a loop.
+pub fn text_to_lines(text: &str, opts: &TextOptions) -> Result<Vec<Line>, TextError> {
+    let unit = opts.height / GLYPH_HEIGHT;
+    let advance = GLYPH_WIDTH * unit + opts.spacing;
+    let mut lines: Vec<Line> = vec![];
+    let (mut a0, mut b0) = (0.0, 0.0);
+    for c in text.chars() {
+        if c == '\n' {
+            a0 = 0.0;
+            b0 -= opts.height * LINE_PITCH_RATIO;
+            continue;
+        }
+        let strokes = glyph(c.to_ascii_uppercase()).ok_or(TextError::UnsupportedChar(c))?;
+        for (i, stroke) in strokes.iter().enumerate() {
+            let closed = stroke.first() == stroke.last();
+            let is_joint = |p: &(f32, f32)| strokes.iter().enumerate()
+                .any(|(j, other)| j != i && other.windows(2).any(|seg| distance_to_segment(*p, seg[0], seg[1]) < JOINT_EPS));
+            lines.push(Line {
+                points: stroke.iter().map(|(x, y)| opts.origin + opts.plane.map(a0 + x * unit, b0 + y * unit)).collect(),
+                curves: vec![],
+                start: !closed && !is_joint(stroke.first().unwrap()),
+                end: !closed && !is_joint(stroke.last().unwrap()),
+            });
+        }
+        a0 += advance;
+    }
+    if lines.is_empty() {
+        return Err(TextError::NoShape);
+    }
+    Ok(lines)
+}
+
+fn distance_to_segment(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len_sq = dx * dx + dy * dy;
+    let t = if len_sq == 0.0 { 0.0 } else { (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len_sq).clamp(0.0, 1.0) };
+    ((p.0 - a.0 - t * dx).powi(2) + (p.1 - a.1 - t * dy).powi(2)).sqrt()
+}
+
+fn glyph(c: char) -> Option<&'static [Stroke]> {
+    let strokes: &'static [Stroke] = match c {
+        ' ' => &[],
+        'A' => &[&[(0.0, 0.0), (2.0, 8.0), (4.0, 0.0)], &[(1.0, 4.0), (3.0, 4.0)]],
+        'B' => &[&[(0.0, 0.0), (0.0, 8.0), (3.0, 8.0), (4.0, 7.0), (4.0, 5.0), (3.0, 4.0), (0.0, 4.0)],
+                 &[(3.0, 4.0), (4.0, 3.0), (4.0, 1.0), (3.0, 0.0), (0.0, 0.0)]],
+        'C' => &[&[(4.0, 7.0), (3.0, 8.0), (1.0, 8.0), (0.0, 7.0), (0.0, 1.0), (1.0, 0.0), (3.0, 0.0), (4.0, 1.0)]],
+        'D' => &[&[(0.0, 0.0), (0.0, 8.0), (2.0, 8.0), (4.0, 6.0), (4.0, 2.0), (2.0, 0.0), (0.0, 0.0)]],
+        'E' => &[&[(4.0, 8.0), (0.0, 8.0), (0.0, 0.0), (4.0, 0.0)], &[(0.0, 4.0), (3.0, 4.0)]],
+        'F' => &[&[(4.0, 8.0), (0.0, 8.0), (0.0, 0.0)], &[(0.0, 4.0), (3.0, 4.0)]],
+        'G' => &[&[(4.0, 7.0), (3.0, 8.0), (1.0, 8.0), (0.0, 7.0), (0.0, 1.0), (1.0, 0.0), (3.0, 0.0), (4.0, 1.0),
+                   (4.0, 4.0), (2.0, 4.0)]],
+        'H' => &[&[(0.0, 0.0), (0.0, 8.0)], &[(4.0, 0.0), (4.0, 8.0)], &[(0.0, 4.0), (4.0, 4.0)]],
+        'I' => &[&[(2.0, 0.0), (2.0, 8.0)], &[(1.0, 8.0), (3.0, 8.0)], &[(1.0, 0.0), (3.0, 0.0)]],
+        'J' => &[&[(4.0, 8.0), (4.0, 1.0), (3.0, 0.0), (1.0, 0.0), (0.0, 1.0), (0.0, 2.0)]],
+        'K' => &[&[(0.0, 0.0), (0.0, 8.0)], &[(4.0, 8.0), (0.0, 4.0), (4.0, 0.0)]],
+        'L' => &[&[(0.0, 8.0), (0.0, 0.0), (4.0, 0.0)]],
+        'M' => &[&[(0.0, 0.0), (0.0, 8.0), (2.0, 4.0), (4.0, 8.0), (4.0, 0.0)]],
+        'N' => &[&[(0.0, 0.0), (0.0, 8.0), (4.0, 0.0), (4.0, 8.0)]],
+        'O' => &[&[(1.0, 0.0), (0.0, 1.0), (0.0, 7.0), (1.0, 8.0), (3.0, 8.0), (4.0, 7.0), (4.0, 1.0), (3.0, 0.0),
+                   (1.0, 0.0)]],
+        'P' => &[&[(0.0, 0.0), (0.0, 8.0), (3.0, 8.0), (4.0, 7.0), (4.0, 5.0), (3.0, 4.0), (0.0, 4.0)]],
+        'Q' => &[&[(1.0, 0.0), (0.0, 1.0), (0.0, 7.0), (1.0, 8.0), (3.0, 8.0), (4.0, 7.0), (4.0, 1.0), (3.0, 0.0),
+                   (1.0, 0.0)], &[(2.0, 2.0), (4.0, 0.0)]],
+        'R' => &[&[(0.0, 0.0), (0.0, 8.0), (3.0, 8.0), (4.0, 7.0), (4.0, 5.0), (3.0, 4.0), (0.0, 4.0)],
+                 &[(2.0, 4.0), (4.0, 0.0)]],
+        'S' => &[&[(4.0, 7.0), (3.0, 8.0), (1.0, 8.0), (0.0, 7.0), (0.0, 5.0), (1.0, 4.0), (3.0, 4.0), (4.0, 3.0),
+                   (4.0, 1.0), (3.0, 0.0), (1.0, 0.0), (0.0, 1.0)]],
+        'T' => &[&[(0.0, 8.0), (4.0, 8.0)], &[(2.0, 8.0), (2.0, 0.0)]],
+        'U' => &[&[(0.0, 8.0), (0.0, 1.0), (1.0, 0.0), (3.0, 0.0), (4.0, 1.0), (4.0, 8.0)]],
+        'V' => &[&[(0.0, 8.0), (2.0, 0.0), (4.0, 8.0)]],
+        'W' => &[&[(0.0, 8.0), (1.0, 0.0), (2.0, 4.0), (3.0, 0.0), (4.0, 8.0)]],
+        'X' => &[&[(0.0, 0.0), (4.0, 8.0)], &[(0.0, 8.0), (4.0, 0.0)]],
+        'Y' => &[&[(0.0, 8.0), (2.0, 4.0), (4.0, 8.0)], &[(2.0, 4.0), (2.0, 0.0)]],
+        'Z' => &[&[(0.0, 8.0), (4.0, 8.0), (0.0, 0.0), (4.0, 0.0)]],
+        '0' => &[&[(1.0, 0.0), (0.0, 1.0), (0.0, 7.0), (1.0, 8.0), (3.0, 8.0), (4.0, 7.0), (4.0, 1.0), (3.0, 0.0),
+                   (1.0, 0.0)], &[(0.0, 1.0), (4.0, 7.0)]],
+        '1' => &[&[(1.0, 6.0), (2.0, 8.0), (2.0, 0.0)], &[(1.0, 0.0), (3.0, 0.0)]],
+        '2' => &[&[(0.0, 7.0), (1.0, 8.0), (3.0, 8.0), (4.0, 7.0), (4.0, 5.0), (0.0, 0.0), (4.0, 0.0)]],
+        '3' => &[&[(0.0, 7.0), (1.0, 8.0), (3.0, 8.0), (4.0, 7.0), (4.0, 5.0), (3.0, 4.0), (4.0, 3.0), (4.0, 1.0),
+                   (3.0, 0.0), (1.0, 0.0), (0.0, 1.0)], &[(1.0, 4.0), (3.0, 4.0)]],
+        '4' => &[&[(3.0, 0.0), (3.0, 8.0), (0.0, 2.0), (4.0, 2.0)]],
+        '5' => &[&[(4.0, 8.0), (0.0, 8.0), (0.0, 4.0), (3.0, 4.0), (4.0, 3.0), (4.0, 1.0), (3.0, 0.0), (0.0, 0.0)]],
+        '6' => &[&[(4.0, 7.0), (3.0, 8.0), (1.0, 8.0), (0.0, 7.0), (0.0, 1.0), (1.0, 0.0), (3.0, 0.0), (4.0, 1.0),
+                   (4.0, 3.0), (3.0, 4.0), (0.0, 4.0)]],
+        '7' => &[&[(0.0, 8.0), (4.0, 8.0), (1.0, 0.0)]],
+        '8' => &[&[(1.0, 4.0), (0.0, 5.0), (0.0, 7.0), (1.0, 8.0), (3.0, 8.0), (4.0, 7.0), (4.0, 5.0), (3.0, 4.0),
+                   (1.0, 4.0)],
+                 &[(1.0, 4.0), (0.0, 3.0), (0.0, 1.0), (1.0, 0.0), (3.0, 0.0), (4.0, 1.0), (4.0, 3.0), (3.0, 4.0),
+                   (1.0, 4.0)]],
+        '9' => &[&[(4.0, 4.0), (1.0, 4.0), (0.0, 5.0), (0.0, 7.0), (1.0, 8.0), (3.0, 8.0), (4.0, 7.0), (4.0, 1.0),
+                   (3.0, 0.0), (1.0, 0.0), (0.0, 1.0)]],
+        '-' => &[&[(1.0, 4.0), (3.0, 4.0)]],
+        '+' => &[&[(2.0, 2.0), (2.0, 6.0)], &[(0.0, 4.0), (4.0, 4.0)]],
+        '/' => &[&[(0.0, 0.0), (4.0, 8.0)]],
+        '.' => &[&[(1.5, 0.0), (2.5, 0.0)]],
+        '!' => &[&[(2.0, 8.0), (2.0, 2.0)], &[(1.5, 0.0), (2.5, 0.0)]],
+        _ => { return None; },
+    };
+    Some(strokes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts() -> TextOptions {
+        TextOptions { height: 8.0, spacing: 1.0, plane: Plane::Yz, origin: PosVec::zero() }
+    }
+
+    #[test]
+    fn glyphs_cover_letters_and_digits() {
+        for c in ('A'..='Z').chain('0'..='9').chain("-+/.!".chars()) {
+            let strokes = glyph(c).unwrap();
+            assert!(!strokes.is_empty(), "{}", c);
+            assert!(strokes.iter().flat_map(|s| s.iter())
+                .all(|&(x, y)| (0.0..=GLYPH_WIDTH).contains(&x) && (0.0..=GLYPH_HEIGHT).contains(&y)), "{}", c);
+        }
+    }
+
+    #[test]
+    fn unsupported_chars_and_blank_text_fail() {
+        assert!(matches!(text_to_lines("A~", &opts()), Err(TextError::UnsupportedChar('~'))));
+        assert!(matches!(text_to_lines(" \n ", &opts()), Err(TextError::NoShape)));
+        assert!(matches!(text_to_lines("", &opts()), Err(TextError::NoShape)));
+    }
+
+    #[test]
+    fn letters_are_placed_and_joined() {
+        // case-insensitive, advancing by the glyph width and the spacing
+        let lines = text_to_lines("tT", &opts()).unwrap();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[2].points[0].y - lines[0].points[0].y, GLYPH_WIDTH + 1.0);
+        // the bar of T has uavs at both ends, the stem joins the bar at its top
+        assert!(lines[0].start && lines[0].end);
+        assert!(!lines[1].start && lines[1].end);
+        // a closed stroke is a loop
+        let lines = text_to_lines("O", &opts()).unwrap();
+        assert!(!lines[0].start && !lines[0].end);
+        // a newline starts a text line below
+        let lines = text_to_lines("-\n-", &opts()).unwrap();
+        assert_eq!(lines[0].points[0].y, lines[1].points[0].y);
+        assert_eq!(lines[0].points[0].z - lines[1].points[0].z, 8.0 * LINE_PITCH_RATIO);
+    }
+}
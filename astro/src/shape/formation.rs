@@ -0,0 +1,27 @@
+// formations kept around the target of a tracking task, in the frame of the target:
+// the target at the origin, x pointing forward and z up.
+
+use super::super::control::msg::{Curve, Line};
+use super::super::kinetics::PosVec;
+
+// a circle of `radius` around the target, `altitude` above it
+pub fn ring(radius: f32, altitude: f32) -> Vec<Line> {
+    let p = |x: f32, y: f32| PosVec { x, y, z: altitude };
+    vec![Line {
+        points: vec![p(radius, 0.0), p(-radius, 0.0), p(radius, 0.0)],
+        curves: vec![Some(Curve::Arc { via: p(0.0, radius) }), Some(Curve::Arc { via: p(0.0, -radius) })],
+        start: false,
+        end: false,
+    }]
+}
+
+// a wedge trailing the target, its apex `gap` behind the target, its two arms `length` long
+// and each `half_angle` off the backward direction
+pub fn wedge(gap: f32, length: f32, half_angle: f32, altitude: f32) -> Vec<Line> {
+    let apex = PosVec { x: -gap, y: 0.0, z: altitude };
+    let arm_end = |side: f32| apex + PosVec { x: -length * half_angle.cos(), y: side * length * half_angle.sin(), z: 0.0 };
+    vec![
+        Line { points: vec![apex, arm_end(1.0)], curves: vec![], start: true, end: true },
+        Line { points: vec![apex, arm_end(-1.0)], curves: vec![], start: false, end: true },  // apex taken by the other arm
+    ]
+}
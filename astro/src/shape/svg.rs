@@ -0,0 +1,464 @@
+// conversion of svg drawings into lines of a task.
+// only the geometry of `path`, `polyline` and `polygon` elements is read,
+// styles and `transform` attributes are ignored.
+
+use std::f32::consts::PI;
+
+use super::super::control::msg::Line;
+use super::Plane;
+
+const MAX_FLATTENING_DEPTH: u32 = 16;
+const MAX_ARC_SEGMENTS: f32 = 65536.0;  // as many chords as a cubic curve flattened to the depth
+
+type Point = (f32, f32);  // svg user units, v axis pointing down
+
+#[derive(Debug)]
+pub enum SvgError {
+    BadPathData(String),  // the `d` attribute of a path cannot be parsed
+    BadPoints(String),  // the `points` attribute of a polyline or polygon cannot be parsed
+    NoShape,  // the drawing has no line
+    BadOptions,  // the scale or the tolerance is not positive
+}
+
+pub struct SvgOptions {
+    pub plane: Plane,
+    pub scale: f32,  // m per svg user unit
+    pub tolerance: f32,  // m, how far the polylines may deviate from the curves
+}
+
+struct Subpath {
+    points: Vec<Point>,
+    closed: bool,
+}
+
+// the bottom-left of the bounding box of the drawing is placed at the origin of the plane.
+// a closed subpath becomes a loop without end points, an open subpath a line with both end points taken.
+pub fn read_svg_lines(svg: &str, opts: &SvgOptions) -> Result<Vec<Line>, SvgError> {
+    // a curve would be cut into infinitely many segments without a positive tolerance
+    if !(opts.scale > 0.0 && opts.scale.is_finite() && opts.tolerance > 0.0 && opts.tolerance.is_finite()) {
+        return Err(SvgError::BadOptions);
+    }
+    let tolerance = opts.tolerance / opts.scale;
+    let mut subpaths: Vec<Subpath> = vec![];
+    for (name, attrs) in scan_tags(svg) {
+        let attr = |key: &str| attrs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+        match name.as_str() {
+            "path" => {
+                let d = attr("d").unwrap_or("");
+                let sps = parse_path_data(d, tolerance).ok_or_else(|| SvgError::BadPathData(d.to_string()))?;
+                subpaths.extend(sps);
+            },
+            "polyline" | "polygon" => {
+                let pts = attr("points").unwrap_or("");
+                let points = parse_points(pts).ok_or_else(|| SvgError::BadPoints(pts.to_string()))?;
+                subpaths.push(Subpath { points, closed: name == "polygon" });
+            },
+            _ => (),
+        }
+    }
+    for sp in &mut subpaths {
+        sp.points.dedup();
+        if sp.points.len() > 2 && sp.points.first() == sp.points.last() {
+            sp.closed = true;
+        }
+        if sp.closed && sp.points.first() != sp.points.last() {
+            sp.points.push(sp.points[0]);
+        }
+    }
+    subpaths.retain(|sp| sp.points.len() >= 2);
+    let all_points = subpaths.iter().flat_map(|sp| sp.points.iter());
+    let u_min = all_points.clone().map(|p| p.0).fold(f32::INFINITY, f32::min);
+    let v_max = all_points.map(|p| p.1).fold(f32::NEG_INFINITY, f32::max);
+    let lines: Vec<Line> = subpaths.into_iter().map(|sp| Line {
+        points: sp.points.iter().map(|(u, v)| opts.plane.map((u - u_min) * opts.scale, (v_max - v) * opts.scale))
+            .collect(),
+        curves: vec![],
+        start: !sp.closed,
+        end: !sp.closed,
+    }).collect();
+    if lines.is_empty() {
+        return Err(SvgError::NoShape);
+    }
+    Ok(lines)
+}
+
+// out: name and attributes of every start tag, comments and other markup skipped
+fn scan_tags(svg: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut tags = vec![];
+    let mut rest = svg;
+    while let Some(i) = rest.find('<') {
+        rest = &rest[i + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |j| &comment[j + 3..]);
+            continue;
+        }
+        let end = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[..end];
+        rest = &rest[end..];
+        if tag.starts_with(['/', '!', '?']) {
+            continue;
+        }
+        let name_end = tag.find(|c: char| c.is_whitespace() || c == '/').unwrap_or(tag.len());
+        tags.push((tag[..name_end].to_string(), scan_attrs(&tag[name_end..])));
+    }
+    tags
+}
+
+fn scan_attrs(mut s: &str) -> Vec<(String, String)> {
+    let mut attrs = vec![];
+    while let Some(eq) = s.find('=') {
+        let key = s[..eq].trim().to_string();
+        let after = s[eq + 1..].trim_start();
+        let quote = match after.chars().next() {
+            Some(q) if q == '"' || q == '\'' => q,
+            _ => { break; },
+        };
+        let value_end = after[1..].find(quote).map_or(after.len(), |j| j + 1);
+        attrs.push((key, after[1..value_end].to_string()));
+        s = after.get(value_end + 1..).unwrap_or("");
+    }
+    attrs
+}
+
+fn parse_points(s: &str) -> Option<Vec<Point>> {
+    let mut lexer = PathLexer::new(s);
+    let mut points = vec![];
+    while !lexer.is_end() {
+        points.push(lexer.point()?);
+    }
+    Some(points)
+}
+
+struct PathLexer<'a> {
+    s: &'a [u8],
+    i: usize,
+}
+
+impl PathLexer<'_> {
+    fn new(s: &str) -> PathLexer<'_> {
+        PathLexer { s: s.as_bytes(), i: 0 }
+    }
+
+    fn skip_separators(&mut self) {
+        while self.i < self.s.len() && (self.s[self.i].is_ascii_whitespace() || self.s[self.i] == b',') {
+            self.i += 1;
+        }
+    }
+
+    fn is_end(&mut self) -> bool {
+        self.skip_separators();
+        self.i >= self.s.len()
+    }
+
+    fn command(&mut self) -> Option<u8> {
+        self.skip_separators();
+        let c = *self.s.get(self.i)?;
+        if c.is_ascii_alphabetic() && c != b'e' && c != b'E' {
+            self.i += 1;
+            Some(c)
+        } else {
+            None
+        }
+    }
+
+    fn number(&mut self) -> Option<f32> {
+        self.skip_separators();
+        let start = self.i;
+        let mut seen_dot = false;
+        let mut seen_exp = false;
+        while let Some(&c) = self.s.get(self.i) {
+            let is_sign = (c == b'+' || c == b'-')
+                && (self.i == start || matches!(self.s[self.i - 1], b'e' | b'E'));
+            match c {
+                b'0'..=b'9' => (),
+                b'.' if !seen_dot && !seen_exp => { seen_dot = true; },
+                b'e' | b'E' if !seen_exp && self.i > start => { seen_exp = true; },
+                _ if is_sign => (),
+                _ => { break; },
+            }
+            self.i += 1;
+        }
+        std::str::from_utf8(&self.s[start..self.i]).ok()?.parse().ok()
+    }
+
+    // arc flags may be written without separators, e.g. "a1 1 0 011 1"
+    fn flag(&mut self) -> Option<bool> {
+        self.skip_separators();
+        let c = *self.s.get(self.i)?;
+        self.i += 1;
+        match c {
+            b'0' => Some(false),
+            b'1' => Some(true),
+            _ => None,
+        }
+    }
+
+    fn point(&mut self) -> Option<Point> {
+        Some((self.number()?, self.number()?))
+    }
+
+    fn has_number(&mut self) -> bool {
+        self.skip_separators();
+        self.s.get(self.i).is_some_and(|c| c.is_ascii_digit() || matches!(c, b'.' | b'-' | b'+'))
+    }
+}
+
+fn add(p: Point, q: Point) -> Point { (p.0 + q.0, p.1 + q.1) }
+
+fn reflect(ctrl: Point, about: Point) -> Point { (2.0 * about.0 - ctrl.0, 2.0 * about.1 - ctrl.1) }
+
+fn parse_path_data(d: &str, tolerance: f32) -> Option<Vec<Subpath>> {
+    let mut lexer = PathLexer::new(d);
+    let mut subpaths: Vec<Subpath> = vec![];
+    let mut points: Vec<Point> = vec![];
+    let mut cur: Point = (0.0, 0.0);
+    let mut start: Point = (0.0, 0.0);
+    let mut last_cubic_ctrl: Option<Point> = None;
+    let mut last_quad_ctrl: Option<Point> = None;
+    let mut cmd: Option<u8> = None;
+    while !lexer.is_end() {
+        match lexer.command() {
+            Some(c) => { cmd = Some(c); },
+            None if cmd.is_some() && lexer.has_number() => (),  // repeated arguments of the last command
+            None => { return None; },
+        }
+        let c = cmd?;
+        let base = if c.is_ascii_lowercase() { cur } else { (0.0, 0.0) };
+        let (mut cubic_ctrl, mut quad_ctrl) = (None, None);
+        match c.to_ascii_uppercase() {
+            b'M' => {
+                if points.len() > 1 {
+                    subpaths.push(Subpath { points, closed: false });
+                }
+                cur = add(base, lexer.point()?);
+                start = cur;
+                points = vec![cur];
+                cmd = Some(if c == b'm' { b'l' } else { b'L' });  // following pairs are implicit line-tos
+            },
+            b'L' => {
+                cur = add(base, lexer.point()?);
+                points.push(cur);
+            },
+            b'H' => {
+                cur = (base.0 + lexer.number()?, cur.1);
+                points.push(cur);
+            },
+            b'V' => {
+                cur = (cur.0, base.1 + lexer.number()?);
+                points.push(cur);
+            },
+            b'C' | b'S' => {
+                let c1 = if c.eq_ignore_ascii_case(&b'C') { add(base, lexer.point()?) }
+                    else { last_cubic_ctrl.map_or(cur, |ctrl| reflect(ctrl, cur)) };
+                let c2 = add(base, lexer.point()?);
+                let p = add(base, lexer.point()?);
+                flatten_cubic([cur, c1, c2, p], tolerance, MAX_FLATTENING_DEPTH, &mut points);
+                cubic_ctrl = Some(c2);
+                cur = p;
+            },
+            b'Q' | b'T' => {
+                let ctrl = if c.eq_ignore_ascii_case(&b'Q') { add(base, lexer.point()?) }
+                    else { last_quad_ctrl.map_or(cur, |ctrl| reflect(ctrl, cur)) };
+                let p = add(base, lexer.point()?);
+                // a quadratic curve is the cubic curve with control points 2/3 of the way to its control point
+                let c1 = (cur.0 + 2.0 / 3.0 * (ctrl.0 - cur.0), cur.1 + 2.0 / 3.0 * (ctrl.1 - cur.1));
+                let c2 = (p.0 + 2.0 / 3.0 * (ctrl.0 - p.0), p.1 + 2.0 / 3.0 * (ctrl.1 - p.1));
+                flatten_cubic([cur, c1, c2, p], tolerance, MAX_FLATTENING_DEPTH, &mut points);
+                quad_ctrl = Some(ctrl);
+                cur = p;
+            },
+            b'A' => {
+                let (rx, ry, rot) = (lexer.number()?, lexer.number()?, lexer.number()?);
+                let (large, sweep) = (lexer.flag()?, lexer.flag()?);
+                let p = add(base, lexer.point()?);
+                flatten_arc(cur, (rx, ry), rot, large, sweep, p, tolerance, &mut points);
+                cur = p;
+            },
+            b'Z' => {
+                if cur != start {
+                    points.push(start);
+                }
+                if points.len() > 1 {
+                    subpaths.push(Subpath { points, closed: true });
+                }
+                cur = start;
+                points = vec![cur];
+                cmd = None;  // no argument follows
+            },
+            _ => { return None; },
+        }
+        last_cubic_ctrl = cubic_ctrl;
+        last_quad_ctrl = quad_ctrl;
+    }
+    if points.len() > 1 {
+        subpaths.push(Subpath { points, closed: false });
+    }
+    Some(subpaths)
+}
+
+// distance of point p from the line through a and b
+fn distance_to_line(p: Point, a: Point, b: Point) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len = (dx * dx + dy * dy).sqrt();
+    if len == 0.0 {
+        return ((p.0 - a.0).powi(2) + (p.1 - a.1).powi(2)).sqrt();
+    }
+    ((p.0 - a.0) * dy - (p.1 - a.1) * dx).abs() / len
+}
+
+// subdivide the curve in halves until its control points are within tolerance of its chord.
+// the start point is not pushed.
+fn flatten_cubic(c: [Point; 4], tolerance: f32, depth: u32, out: &mut Vec<Point>) {
+    let flat = distance_to_line(c[1], c[0], c[3]) <= tolerance && distance_to_line(c[2], c[0], c[3]) <= tolerance;
+    if flat || depth == 0 {
+        out.push(c[3]);
+        return;
+    }
+    let mid = |p: Point, q: Point| ((p.0 + q.0) / 2.0, (p.1 + q.1) / 2.0);
+    let (p01, p12, p23) = (mid(c[0], c[1]), mid(c[1], c[2]), mid(c[2], c[3]));
+    let (p012, p123) = (mid(p01, p12), mid(p12, p23));
+    let p0123 = mid(p012, p123);
+    flatten_cubic([c[0], p01, p012, p0123], tolerance, depth - 1, out);
+    flatten_cubic([p0123, p123, p23, c[3]], tolerance, depth - 1, out);
+}
+
+// elliptical arc given by its end points, as in svg path data.
+// the start point is not pushed.
+#[allow(clippy::too_many_arguments)]
+fn flatten_arc(p0: Point, radii: (f32, f32), rot_deg: f32, large: bool, sweep: bool, p1: Point,
+               tolerance: f32, out: &mut Vec<Point>) {
+    let (mut rx, mut ry) = (radii.0.abs(), radii.1.abs());
+    if rx == 0.0 || ry == 0.0 {
+        out.push(p1);
+        return;
+    }
+    // conversion from end points to centre, svg 1.1 appendix f.6.5
+    let (sin, cos) = rot_deg.to_radians().sin_cos();
+    let (dx, dy) = ((p0.0 - p1.0) / 2.0, (p0.1 - p1.1) / 2.0);
+    let (x1, y1) = (cos * dx + sin * dy, -sin * dx + cos * dy);
+    let lambda = (x1 / rx).powi(2) + (y1 / ry).powi(2);
+    if lambda > 1.0 {  // radii too small to reach the end point, scaled up
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+    let num = (rx * ry).powi(2) - (rx * y1).powi(2) - (ry * x1).powi(2);
+    let den = (rx * y1).powi(2) + (ry * x1).powi(2);
+    let sign = if large == sweep { -1.0 } else { 1.0 };
+    let coef = sign * (num / den).max(0.0).sqrt();
+    let (cx1, cy1) = (coef * rx * y1 / ry, -coef * ry * x1 / rx);
+    let (cx, cy) = (cos * cx1 - sin * cy1 + (p0.0 + p1.0) / 2.0, sin * cx1 + cos * cy1 + (p0.1 + p1.1) / 2.0);
+    let angle = |u: Point, v: Point| (u.0 * v.1 - u.1 * v.0).atan2(u.0 * v.0 + u.1 * v.1);
+    let u = ((x1 - cx1) / rx, (y1 - cy1) / ry);
+    let v = ((-x1 - cx1) / rx, (-y1 - cy1) / ry);
+    let theta = angle((1.0, 0.0), u);
+    let mut dtheta = angle(u, v);
+    if !sweep && dtheta > 0.0 {
+        dtheta -= 2.0 * PI;
+    } else if sweep && dtheta < 0.0 {
+        dtheta += 2.0 * PI;
+    }
+    // each chord deviates from the arc by at most the tolerance
+    let r = rx.max(ry);
+    let step = if tolerance < r { 2.0 * (1.0 - tolerance / r).acos() } else { PI / 2.0 };
+    let n = (dtheta.abs() / step).ceil().clamp(1.0, MAX_ARC_SEGMENTS) as u32;
+    for i in 1..n {
+        let t = theta + dtheta * i as f32 / n as f32;
+        let (st, ct) = t.sin_cos();
+        out.push((cx + rx * ct * cos - ry * st * sin, cy + rx * ct * sin + ry * st * cos));
+    }
+    out.push(p1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(tolerance: f32) -> SvgOptions {
+        SvgOptions { plane: Plane::Xy, scale: 1.0, tolerance }
+    }
+
+    fn near(p: Point, q: Point) -> bool {
+        (p.0 - q.0).abs() < 1e-3 && (p.1 - q.1).abs() < 1e-3
+    }
+
+    #[test]
+    fn path_commands_absolute_and_relative() {
+        let sps = parse_path_data("M 1,1 L3 1 h2 v-2 l-1-1 H1 V1 Z m10 0 10 0", 0.1).unwrap();
+        assert_eq!(sps.len(), 2);
+        assert!(sps[0].closed);
+        let expected = [(1.0, 1.0), (3.0, 1.0), (5.0, 1.0), (5.0, -1.0), (4.0, -2.0), (1.0, -2.0), (1.0, 1.0)];
+        assert_eq!(sps[0].points, expected.to_vec());
+        // a relative move after close starts from the start of the closed subpath, followed by implicit line-tos
+        assert!(!sps[1].closed);
+        assert_eq!(sps[1].points, vec![(11.0, 1.0), (21.0, 1.0)]);
+    }
+
+    #[test]
+    fn numbers_without_separators() {
+        let sps = parse_path_data("M.5.5L-1e1-2.5e-1", 0.1).unwrap();
+        assert_eq!(sps[0].points, vec![(0.5, 0.5), (-10.0, -0.25)]);
+        assert!(parse_path_data("M0 0 L1", 0.1).is_none());
+        assert!(parse_path_data("0 0", 0.1).is_none());
+        assert!(parse_path_data("M0 0 X1 1", 0.1).is_none());
+    }
+
+    #[test]
+    fn cubic_is_flattened_within_tolerance() {
+        let tolerance = 0.01;
+        let sps = parse_path_data("M0 0 C0 10 10 10 10 0", tolerance).unwrap();
+        let points = &sps[0].points;
+        assert!(points.len() > 4);
+        assert!(near(*points.last().unwrap(), (10.0, 0.0)));
+        // the curve is symmetric and peaks at 7.5
+        let peak = points.iter().map(|p| p.1).fold(f32::NEG_INFINITY, f32::max);
+        assert!((peak - 7.5).abs() <= tolerance);
+        // a smooth curve reflects the last control point
+        let sps = parse_path_data("M0 0 C0 10 10 10 10 0 S20 -10 20 0", tolerance).unwrap();
+        let trough = sps[0].points.iter().map(|p| p.1).fold(f32::INFINITY, f32::min);
+        assert!((trough + 7.5).abs() <= tolerance);
+    }
+
+    #[test]
+    fn arc_is_flattened_on_its_circle() {
+        for (d, centre) in [("M0 0 A5 5 0 0 1 10 0", (5.0, 0.0)), ("M0 0 a5 5 0 1 0 10 0", (5.0, 0.0)),
+                            ("M0 0 A1 1 0 0 1 10 0", (5.0, 0.0))] {  // radii scaled up to reach the end point
+            let sps = parse_path_data(d, 0.01).unwrap();
+            let points = &sps[0].points;
+            assert!(points.len() > 10, "{}", d);
+            assert!(near(*points.last().unwrap(), (10.0, 0.0)), "{}", d);
+            let r = |p: &Point| ((p.0 - centre.0).powi(2) + (p.1 - centre.1).powi(2)).sqrt();
+            assert!(points.iter().all(|p| (r(p) - 5.0).abs() < 1e-3), "{}", d);
+        }
+        // the sweep flag decides the side
+        let side = |d: &str| parse_path_data(d, 0.01).unwrap()[0].points[1].1.signum();
+        assert_eq!(side("M0 0 A5 5 0 0 1 10 0"), -side("M0 0 A5 5 0 0 0 10 0"));
+        // compact flags
+        let sps = parse_path_data("M0 0a5 5 0 0110 0", 0.01).unwrap();
+        assert!(near(*sps[0].points.last().unwrap(), (10.0, 0.0)));
+    }
+
+    #[test]
+    fn drawing_is_placed_at_origin() {
+        let svg = r#"<svg><!-- <path d="M0 0 L1 1"/> --><polygon points="10,20 14,20 14,24"/>
+            <polyline points='10 30 12 30'/></svg>"#;
+        let lines = read_svg_lines(svg, &opts(0.1)).unwrap();
+        assert_eq!(lines.len(), 2);
+        // a polygon is a loop, a polyline takes uavs at its ends
+        assert!(!lines[0].start && !lines[0].end && lines[0].points.len() == 4);
+        assert!(lines[1].start && lines[1].end);
+        // v axis flipped, bottom-left at the origin
+        assert_eq!((lines[1].points[0].x, lines[1].points[0].y), (0.0, 0.0));
+        assert_eq!((lines[0].points[0].x, lines[0].points[0].y), (0.0, 10.0));
+    }
+
+    #[test]
+    fn bad_options_and_drawings_fail() {
+        let svg = r#"<svg><path d="M0 0 A5 5 0 0 1 10 0"/></svg>"#;
+        for tolerance in [0.0, -1.0, f32::NAN] {
+            assert!(matches!(read_svg_lines(svg, &opts(tolerance)), Err(SvgError::BadOptions)));
+        }
+        assert!(matches!(read_svg_lines("<svg></svg>", &opts(0.1)), Err(SvgError::NoShape)));
+        assert!(matches!(read_svg_lines(r#"<path d="M0 0 Q"/>"#, &opts(0.1)), Err(SvgError::BadPathData(_))));
+        assert!(matches!(read_svg_lines(r#"<polyline points="1 2 3"/>"#, &opts(0.1)), Err(SvgError::BadPoints(_))));
+    }
+}